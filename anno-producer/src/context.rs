@@ -0,0 +1,197 @@
+use std::{collections::BTreeMap, env, fs, ops::RangeInclusive, path::PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use serde_json::Value;
+
+use crate::output::Protocol;
+
+/// Everything Anno tells a producer about the file being annotated
+#[derive(Clone, Debug)]
+pub struct ProducerContext {
+    /// Name the producer was invoked as (`ANNO_PRODUCER`)
+    pub producer: String,
+    /// Absolute path to the content being annotated (`ANNO_TARGET`), which
+    /// may be a temporary copy (such as for an unsaved editor buffer)
+    pub target: PathBuf,
+    /// Absolute path of the file being annotated as the user knows it
+    /// (`ANNO_TARGET_NAME`), which is the same as `target` unless the content
+    /// came from elsewhere
+    pub target_name: PathBuf,
+    /// Number of lines in the file being annotated (`ANNO_TARGET_LINES`)
+    pub line_count: usize,
+    /// Data source given in the producer URI, which may be empty
+    /// (`ANNO_SOURCE`)
+    pub source: String,
+    /// Query parameters given in the producer URI, with all values for each
+    /// key (`ANNO_PARAMS`)
+    pub params: BTreeMap<String, Vec<String>>,
+    /// Lines (1-based, inclusive) that will be shown, or `None` when the
+    /// whole file is shown (`ANNO_LINE_RANGES`)
+    pub line_ranges: Option<Vec<RangeInclusive<usize>>>,
+    /// Every file being annotated when Anno annotates several at once
+    /// (`ANNO_TARGETS`), starting with `target`, or empty otherwise
+    pub targets: Vec<Target>,
+    /// Output protocol to use, negotiated via `ANNO_PROTOCOLS`
+    pub protocol: Protocol,
+}
+
+/// One of several files being annotated at once
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Target {
+    /// Absolute path to the file
+    pub path: PathBuf,
+    /// Number of lines in the file
+    pub line_count: usize,
+    /// Lines (1-based, inclusive) that will be shown, or `None` when the
+    /// whole file is shown
+    pub line_ranges: Option<Vec<RangeInclusive<usize>>>,
+}
+
+impl ProducerContext {
+    /// Reads the context Anno supplies via environment variables
+    pub fn from_env() -> Result<ProducerContext> {
+        ProducerContext::from_vars(|name| env::var(name).ok())
+    }
+
+    /// Reads the context from variables looked up by name with `var`, as
+    /// they would be set by Anno
+    pub fn from_vars(var: impl Fn(&str) -> Option<String>) -> Result<ProducerContext> {
+        let target = required_var(&var, "ANNO_TARGET")?;
+        let line_count = required_var(&var, "ANNO_TARGET_LINES")?;
+        let line_count = line_count
+            .parse()
+            .with_context(|| format!("`ANNO_TARGET_LINES` is not a number ({})", line_count))?;
+        let protocols = var("ANNO_PROTOCOLS").unwrap_or_default();
+        let params = match var("ANNO_PARAMS") {
+            Some(params) => serde_json::from_str(&params)
+                .with_context(|| format!("`ANNO_PARAMS` is not valid ({})", params))?,
+            None => BTreeMap::new(),
+        };
+        let line_ranges = match var("ANNO_LINE_RANGES") {
+            Some(ranges) => Some(
+                parse_line_ranges(&ranges)
+                    .with_context(|| format!("`ANNO_LINE_RANGES` is not valid ({})", ranges))?,
+            ),
+            None => None,
+        };
+        let targets = match var("ANNO_TARGETS") {
+            Some(path) => read_targets(&path)
+                .with_context(|| format!("`ANNO_TARGETS` is not valid ({})", path))?,
+            None => Vec::new(),
+        };
+        Ok(ProducerContext {
+            producer: var("ANNO_PRODUCER").unwrap_or_default(),
+            target_name: PathBuf::from(var("ANNO_TARGET_NAME").unwrap_or_else(|| target.clone())),
+            target: PathBuf::from(target),
+            line_count,
+            source: var("ANNO_SOURCE").unwrap_or_default(),
+            params,
+            line_ranges,
+            targets,
+            protocol: Protocol::negotiate(&protocols),
+        })
+    }
+
+    /// Context for annotating just one of the `targets`
+    pub fn for_target(&self, target: &Target) -> ProducerContext {
+        ProducerContext {
+            target: target.path.clone(),
+            target_name: target.path.clone(),
+            line_count: target.line_count,
+            line_ranges: target.line_ranges.clone(),
+            targets: Vec::new(),
+            ..self.clone()
+        }
+    }
+
+    /// Returns the data source, or an error naming what it should be (e.g.
+    /// "Path to debug info") when none was given
+    pub fn require_source(&self, description: &str) -> Result<&str> {
+        if self.source.is_empty() {
+            return Err(anyhow!("{} is required", description));
+        }
+        Ok(&self.source)
+    }
+
+    /// Returns the last value given for a query parameter, if any
+    pub fn param(&self, key: &str) -> Option<&str> {
+        self.params
+            .get(key)
+            .and_then(|values| values.last())
+            .map(String::as_str)
+    }
+
+    /// Returns all values given for a query parameter, which is empty if the
+    /// parameter was not given
+    pub fn param_values(&self, key: &str) -> &[String] {
+        self.params.get(key).map_or(&[], Vec::as_slice)
+    }
+
+    /// Whether annotations for `line` (1-based) will be shown, so producers
+    /// can skip expensive work for other lines
+    pub fn wants_line(&self, line: usize) -> bool {
+        self.line_ranges
+            .as_ref()
+            .is_none_or(|ranges| ranges.iter().any(|range| range.contains(&line)))
+    }
+
+    /// Returns an error unless the file being annotated has the given
+    /// extension
+    pub fn require_extension(&self, extension: &str) -> Result<()> {
+        if self.target.extension().is_none_or(|e| e != extension) {
+            return Err(anyhow!(
+                "Only `.{}` files are currently supported",
+                extension
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Parses comma-separated line ranges such as `120-180,200-200`
+fn parse_line_ranges(ranges: &str) -> Result<Vec<RangeInclusive<usize>>> {
+    ranges
+        .split(',')
+        .filter(|range| !range.is_empty())
+        .map(|range| {
+            let (start, end) = range.split_once('-').unwrap_or((range, range));
+            Ok(start.parse()?..=end.parse()?)
+        })
+        .collect()
+}
+
+/// Reads the list of targets from the JSON file named by `ANNO_TARGETS`
+fn read_targets(path: &str) -> Result<Vec<Target>> {
+    let targets: Value = serde_json::from_str(&fs::read_to_string(path)?)?;
+    targets
+        .as_array()
+        .ok_or_else(|| anyhow!("Expected a list of targets"))?
+        .iter()
+        .map(|target| {
+            let path = target["path"]
+                .as_str()
+                .ok_or_else(|| anyhow!("Target has no `path`"))?;
+            let line_count = target["lines"]
+                .as_u64()
+                .ok_or_else(|| anyhow!("Target has no `lines`"))?;
+            let line_ranges = match target["line_ranges"].as_str() {
+                Some(ranges) => Some(parse_line_ranges(ranges)?),
+                None => None,
+            };
+            Ok(Target {
+                path: PathBuf::from(path),
+                line_count: line_count as usize,
+                line_ranges,
+            })
+        })
+        .collect()
+}
+
+fn required_var(var: impl Fn(&str) -> Option<String>, name: &str) -> Result<String> {
+    var(name).with_context(|| {
+        format!(
+            "`{}` is not set (producers are meant to be run by Anno)",
+            name
+        )
+    })
+}
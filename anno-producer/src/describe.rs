@@ -0,0 +1,255 @@
+use std::{collections::BTreeMap, io::Write};
+
+use anyhow::Result;
+use serde_json::{json, Value};
+
+/// Argument Anno passes to ask a producer to describe itself
+pub const DESCRIBE_ARG: &str = "--anno-describe";
+
+/// Description of a producer, printed as JSON when Anno runs it with
+/// `--anno-describe` so that `anno producers list` can show it and Anno can
+/// check producer specs before running anything
+///
+/// ```
+/// use anno_producer::{Description, ValueKind};
+///
+/// let description = Description::new("debug-line-table", "Marks lines in the line table")
+///     .requires_source("Path to debug info")
+///     .param("function", "Only annotate this function")
+///     .values(ValueKind::Boolean)
+///     .legend("x", "Line is in the line table");
+/// ```
+#[derive(Clone, Debug)]
+pub struct Description {
+    name: String,
+    summary: String,
+    source: Source,
+    params: Vec<Param>,
+    env: Option<Vec<String>>,
+    diffable: bool,
+    kind: Option<ValueKind>,
+    width: Option<usize>,
+    align: Option<Align>,
+    legend: BTreeMap<String, String>,
+}
+
+/// Kind of value in a producer's annotations, which affects how Anno
+/// compares and displays them
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ValueKind {
+    /// Marks (such as `x`) compared only by whether they are present
+    Boolean,
+    /// Sets of tokens (such as variable names) compared regardless of order
+    Set,
+    /// Numbers, which are right-aligned
+    Number,
+    Text,
+}
+
+/// Alignment of annotations within a producer's column
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Align {
+    Left,
+    Right,
+}
+
+#[derive(Clone, Debug)]
+enum Source {
+    None,
+    Optional(String),
+    Required(String),
+}
+
+#[derive(Clone, Debug)]
+struct Param {
+    name: String,
+    description: String,
+    required: bool,
+}
+
+impl Description {
+    /// Describes a producer which takes no data source or parameters
+    pub fn new(name: &str, summary: &str) -> Description {
+        Description {
+            name: name.to_string(),
+            summary: summary.to_string(),
+            source: Source::None,
+            params: Vec::new(),
+            env: None,
+            diffable: true,
+            kind: None,
+            width: None,
+            align: None,
+            legend: BTreeMap::new(),
+        }
+    }
+
+    /// The producer must be given a data source, such as a path to debug info
+    pub fn requires_source(mut self, description: &str) -> Description {
+        self.source = Source::Required(description.to_string());
+        self
+    }
+
+    /// The producer may be given a data source
+    pub fn optional_source(mut self, description: &str) -> Description {
+        self.source = Source::Optional(description.to_string());
+        self
+    }
+
+    /// The producer accepts the query parameter `name`
+    pub fn param(mut self, name: &str, description: &str) -> Description {
+        self.params.push(Param {
+            name: name.to_string(),
+            description: description.to_string(),
+            required: false,
+        });
+        self
+    }
+
+    /// The producer must be given the query parameter `name`
+    pub fn required_param(mut self, name: &str, description: &str) -> Description {
+        self.params.push(Param {
+            name: name.to_string(),
+            description: description.to_string(),
+            required: true,
+        });
+        self
+    }
+
+    /// The producer's output depends on the environment variable `name`
+    ///
+    /// Once any are listed, Anno only considers these (along with `PATH` and
+    /// its own variables) when deciding whether cached output can be reused,
+    /// rather than the whole environment.
+    pub fn reads_env(mut self, name: &str) -> Description {
+        self.env.get_or_insert_with(Vec::new).push(name.to_string());
+        self
+    }
+
+    /// Comparing the producer's annotations with others is not meaningful
+    /// (as for line numbers), so Anno leaves them out of diffs by default
+    pub fn not_diffable(mut self) -> Description {
+        self.diffable = false;
+        self
+    }
+
+    /// Annotations are all values of this kind
+    pub fn values(mut self, kind: ValueKind) -> Description {
+        self.kind = Some(kind);
+        self
+    }
+
+    /// Preferred cap on the column width, used unless the user gives one
+    pub fn width(mut self, width: usize) -> Description {
+        self.width = Some(width);
+        self
+    }
+
+    /// Aligns annotations within the column, instead of the default for their
+    /// kind (right for numbers, otherwise left)
+    pub fn align(mut self, align: Align) -> Description {
+        self.align = Some(align);
+        self
+    }
+
+    /// Explains what a symbol used in annotations (such as `x`) means
+    pub fn legend(mut self, symbol: &str, meaning: &str) -> Description {
+        self.legend.insert(symbol.to_string(), meaning.to_string());
+        self
+    }
+
+    fn to_json(&self) -> Value {
+        let (source, source_description) = match &self.source {
+            Source::None => ("none", None),
+            Source::Optional(description) => ("optional", Some(description)),
+            Source::Required(description) => ("required", Some(description)),
+        };
+        let params: Vec<Value> = self
+            .params
+            .iter()
+            .map(|param| {
+                json!({
+                    "name": param.name,
+                    "description": param.description,
+                    "required": param.required,
+                })
+            })
+            .collect();
+        json!({
+            "describe": 1,
+            "name": self.name,
+            "description": self.summary,
+            "source": source,
+            "source_description": source_description,
+            "params": params,
+            "env": self.env,
+            "protocols": ["jsonl/1", "lines"],
+            "capabilities": {
+                "diffable": self.diffable,
+                "kind": self.kind.map(|kind| match kind {
+                    ValueKind::Boolean => "boolean",
+                    ValueKind::Set => "set",
+                    ValueKind::Number => "number",
+                    ValueKind::Text => "text",
+                }),
+                "width": self.width,
+                "align": self.align.map(|align| match align {
+                    Align::Left => "left",
+                    Align::Right => "right",
+                }),
+                "legend": self.legend,
+            },
+        })
+    }
+
+    /// Writes the description as Anno expects it from `--anno-describe`
+    pub fn write(&self, out: &mut impl Write) -> Result<()> {
+        writeln!(out, "{}", self.to_json())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn description_written_as_json() {
+        let description = Description::new("example", "Example producer")
+            .requires_source("Path to data")
+            .param("function", "Function to annotate")
+            .required_param("mode", "Mode to use")
+            .reads_env("CC");
+        let mut out = Vec::new();
+        description.write(&mut out).unwrap();
+        let written: Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(written["describe"], 1);
+        assert_eq!(written["source"], "required");
+        assert_eq!(written["source_description"], "Path to data");
+        assert_eq!(written["params"][0]["name"], "function");
+        assert_eq!(written["params"][0]["required"], false);
+        assert_eq!(written["params"][1]["required"], true);
+        assert_eq!(written["env"][0], "CC");
+        assert_eq!(written["protocols"][0], "jsonl/1");
+        assert_eq!(written["capabilities"]["diffable"], true);
+        assert_eq!(written["capabilities"]["kind"], Value::Null);
+    }
+
+    #[test]
+    fn capabilities_written() {
+        let description = Description::new("example", "Example producer")
+            .not_diffable()
+            .values(ValueKind::Number)
+            .width(6)
+            .legend("-", "Unknown");
+        let mut out = Vec::new();
+        description.write(&mut out).unwrap();
+        let written: Value = serde_json::from_slice(&out).unwrap();
+        let capabilities = &written["capabilities"];
+        assert_eq!(capabilities["diffable"], false);
+        assert_eq!(capabilities["kind"], "number");
+        assert_eq!(capabilities["width"], 6);
+        assert_eq!(capabilities["align"], Value::Null);
+        assert_eq!(capabilities["legend"]["-"], "Unknown");
+    }
+}
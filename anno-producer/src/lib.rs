@@ -0,0 +1,108 @@
+//! Helpers for writing annotation data producers for Anno.
+//!
+//! Anno runs each producer as a separate `anno-<name>` command, describing
+//! the file to annotate via `ANNO`-prefixed environment variables. This crate
+//! reads those into a [`ProducerContext`], collects annotations in an
+//! [`Output`], and writes them in whichever protocol Anno supports. Each
+//! producer also gives a [`Description`] of itself, which Anno asks for by
+//! running it with `--anno-describe`.
+//!
+//! ```no_run
+//! use std::process::ExitCode;
+//!
+//! use anno_producer::{Description, Output};
+//!
+//! fn main() -> ExitCode {
+//!     let description = Description::new("example", "Marks the first three lines");
+//!     anno_producer::run(&description, |context| {
+//!         let mut output = Output::new(context);
+//!         output.mark_lines([1, 2, 3], "x");
+//!         Ok(output)
+//!     })
+//! }
+//! ```
+
+mod context;
+mod describe;
+mod output;
+
+use std::{env, io, process::ExitCode};
+
+use log::debug;
+
+pub use crate::{
+    context::{ProducerContext, Target},
+    describe::{Align, Description, ValueKind, DESCRIBE_ARG},
+    output::{Output, Position, Protocol, Range},
+};
+
+/// Runs a producer, handling setup and reporting in the same way for all
+/// producers
+///
+/// Initialises logging, reads the producer context from the environment,
+/// calls `produce`, and writes the resulting output to stdout. Any errors are
+/// reported on stderr with a failing exit code. When run with
+/// `--anno-describe`, writes `description` instead.
+pub fn run<F>(description: &Description, produce: F) -> ExitCode
+where
+    F: FnOnce(&ProducerContext) -> anyhow::Result<Output>,
+{
+    env_logger::init();
+    if describe_requested() {
+        return report(description.write(&mut io::stdout().lock()));
+    }
+
+    report(ProducerContext::from_env().and_then(|context| {
+        debug!("Context: {:?}", context);
+        let output = produce(&context)?;
+        output.write(&mut io::stdout().lock())
+    }))
+}
+
+/// Runs a producer which can annotate several files in one run, calling
+/// `produce` for each file so that expensive setup (such as loading debug
+/// info) can be shared between calls
+///
+/// When Anno annotates several files at once (via `ANNO_TARGETS`) and offers
+/// the `jsonl/1` protocol, `produce` is called with the context for each file
+/// in turn and all output is written together. Otherwise, this behaves like
+/// [`run`].
+pub fn run_each<F>(description: &Description, mut produce: F) -> ExitCode
+where
+    F: FnMut(&ProducerContext) -> anyhow::Result<Output>,
+{
+    env_logger::init();
+    if describe_requested() {
+        return report(description.write(&mut io::stdout().lock()));
+    }
+
+    report(ProducerContext::from_env().and_then(|context| {
+        debug!("Context: {:?}", context);
+        if context.targets.is_empty() || context.protocol != Protocol::JsonLines {
+            let output = produce(&context)?;
+            return output.write(&mut io::stdout().lock());
+        }
+        let mut outputs = Vec::new();
+        for target in &context.targets {
+            let output = produce(&context.for_target(target))?;
+            outputs.push((target.path.clone(), output));
+        }
+        Output::write_targets(&outputs, &mut io::stdout().lock())
+    }))
+}
+
+/// Whether Anno ran the producer with `--anno-describe`
+fn describe_requested() -> bool {
+    env::args().nth(1).as_deref() == Some(DESCRIBE_ARG)
+}
+
+/// Reports any error on stderr, returning the exit code to use
+fn report(result: anyhow::Result<()>) -> ExitCode {
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {:#}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,241 @@
+use std::{io::Write, path::PathBuf};
+
+use anyhow::{anyhow, Result};
+use log::debug;
+use serde_json::json;
+
+use crate::context::ProducerContext;
+
+/// Formats for producer output understood by Anno
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Protocol {
+    /// One line of text for each line of the file being annotated
+    Lines,
+    /// JSON Lines records for only those lines with annotations
+    JsonLines,
+}
+
+impl Protocol {
+    /// Picks the first protocol we support from the list Anno offers via
+    /// `ANNO_PROTOCOLS`, falling back to `Lines` for older versions of Anno
+    pub fn negotiate(offered: &str) -> Protocol {
+        offered
+            .split(',')
+            .find_map(|protocol| match protocol.trim() {
+                "jsonl/1" => Some(Protocol::JsonLines),
+                "lines" => Some(Protocol::Lines),
+                _ => None,
+            })
+            .unwrap_or(Protocol::Lines)
+    }
+}
+
+/// Position within the file being annotated
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Position {
+    /// Line number (1-based)
+    pub line: usize,
+    /// Column number (1-based, counting UTF-8 bytes), if not referring to the
+    /// whole line
+    pub column: Option<usize>,
+}
+
+/// Region of the file being annotated, including both `start` and `end`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Range {
+    pub start: Position,
+    pub end: Position,
+}
+
+/// Annotations collected by a producer, written in the negotiated protocol
+#[derive(Debug)]
+pub struct Output {
+    protocol: Protocol,
+    line_count: usize,
+    /// Values for each line, in the order added and without repeats
+    lines: Vec<Vec<String>>,
+    /// Annotations for ranges of the file
+    ranges: Vec<(Range, String)>,
+    /// Whether values are written as sets of tokens
+    sets: bool,
+}
+
+impl Output {
+    pub fn new(context: &ProducerContext) -> Output {
+        Output::with_protocol(context.protocol, context.line_count)
+    }
+
+    pub fn with_protocol(protocol: Protocol, line_count: usize) -> Output {
+        Output {
+            protocol,
+            line_count,
+            lines: vec![Vec::new(); line_count],
+            ranges: Vec::new(),
+            sets: false,
+        }
+    }
+
+    /// Writes the values for each line as a set (such as of variable names),
+    /// so that Anno compares them regardless of order when diffing
+    pub fn use_sets(&mut self) {
+        self.sets = true;
+    }
+
+    /// Adds a value to a (1-based) line, ignoring lines outside the file
+    pub fn add(&mut self, line: usize, value: impl Into<String>) {
+        let value = value.into();
+        if line < 1 || line > self.line_count {
+            debug!(
+                "Ignoring value `{}` for line {} outside target",
+                value, line
+            );
+            return;
+        }
+        let values = &mut self.lines[line - 1];
+        if !values.contains(&value) {
+            values.push(value);
+        }
+    }
+
+    /// Adds the same value (such as `x`) to each of a set of lines
+    pub fn mark_lines(&mut self, lines: impl IntoIterator<Item = usize>, value: &str) {
+        for line in lines {
+            self.add(line, value);
+        }
+    }
+
+    /// Adds a list of values (such as variable names) to a line
+    pub fn add_list<I, V>(&mut self, line: usize, values: I)
+    where
+        I: IntoIterator<Item = V>,
+        V: Into<String>,
+    {
+        for value in values {
+            self.add(line, value);
+        }
+    }
+
+    /// Adds a value to a range of the file, clipping the range to the lines
+    /// of the file and ignoring it if it lies entirely outside
+    ///
+    /// When Anno does not support range annotations, the value is added to
+    /// every line in the range instead.
+    pub fn add_range(&mut self, mut range: Range, value: impl Into<String>) {
+        let value = value.into();
+        if range.end.line < 1 || range.start.line > self.line_count {
+            debug!(
+                "Ignoring value `{}` for lines {}-{} outside target",
+                value, range.start.line, range.end.line
+            );
+            return;
+        }
+        // Clipped ends cover whole lines, since their columns no longer apply
+        if range.start.line < 1 {
+            range.start = Position {
+                line: 1,
+                column: None,
+            };
+        }
+        if range.end.line > self.line_count {
+            range.end = Position {
+                line: self.line_count,
+                column: None,
+            };
+        }
+        match self.protocol {
+            Protocol::JsonLines => self.ranges.push((range, value)),
+            Protocol::Lines => {
+                for line in range.start.line..=range.end.line {
+                    self.add(line, value.clone());
+                }
+            }
+        }
+    }
+
+    /// Writes all collected annotations
+    pub fn write(self, out: &mut impl Write) -> Result<()> {
+        match self.protocol {
+            Protocol::Lines => {
+                for values in &self.lines {
+                    if values.is_empty() {
+                        writeln!(out, " ")?;
+                    } else {
+                        writeln!(out, "{}", values.join(" "))?;
+                    }
+                }
+            }
+            Protocol::JsonLines => {
+                writeln!(out, "{}", json!({ "protocol": "jsonl", "version": 1 }))?;
+                for record in self.records() {
+                    writeln!(out, "{}", record)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the annotations for several targets (from `ANNO_TARGETS`)
+    /// together, so that Anno need not run the producer again for each
+    ///
+    /// Requires the `jsonl/1` protocol.
+    pub fn write_targets(outputs: &[(PathBuf, Output)], out: &mut impl Write) -> Result<()> {
+        if outputs
+            .iter()
+            .any(|(_, output)| output.protocol != Protocol::JsonLines)
+        {
+            return Err(anyhow!(
+                "Annotating several targets at once requires JSON Lines output"
+            ));
+        }
+        writeln!(
+            out,
+            "{}",
+            json!({ "protocol": "jsonl", "version": 1, "targets": true })
+        )?;
+        for (target, output) in outputs {
+            for mut record in output.records() {
+                record["target"] = json!(target);
+                writeln!(out, "{}", record)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Output {
+    /// JSON Lines records for all collected annotations
+    fn records(&self) -> Vec<serde_json::Value> {
+        let mut records = Vec::new();
+        for (i, values) in self.lines.iter().enumerate() {
+            if values.is_empty() {
+                continue;
+            }
+            records.push(json!({ "line": i + 1, "value": self.value_json(values) }));
+        }
+        for (range, value) in &self.ranges {
+            records.push(json!({
+                "range": {
+                    "start": position_json(&range.start),
+                    "end": position_json(&range.end),
+                },
+                "value": self.value_json(std::slice::from_ref(value)),
+            }));
+        }
+        records
+    }
+
+    fn value_json(&self, values: &[String]) -> serde_json::Value {
+        if self.sets {
+            json!(values)
+        } else {
+            json!(values.join(" "))
+        }
+    }
+}
+
+fn position_json(position: &Position) -> serde_json::Value {
+    match position.column {
+        Some(column) => json!({ "line": position.line, "column": column }),
+        None => json!({ "line": position.line }),
+    }
+}
@@ -0,0 +1,101 @@
+use std::{collections::HashMap, env, fs, path::PathBuf};
+
+use anno_producer::{ProducerContext, Target};
+
+/// Context read from the given variables rather than the (shared) process
+/// environment, so that tests can run in parallel
+fn read_context(vars: &[(&str, &str)]) -> ProducerContext {
+    let vars: HashMap<String, String> = vars
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+    ProducerContext::from_vars(|name| vars.get(name).cloned()).unwrap()
+}
+
+#[test]
+fn params_read_from_env() {
+    let context = read_context(&[
+        ("ANNO_TARGET", "/tmp/example.c"),
+        ("ANNO_TARGET_LINES", "12"),
+        (
+            "ANNO_PARAMS",
+            r#"{"function":["bob","alice"],"max-depth":["2"]}"#,
+        ),
+    ]);
+    assert_eq!(context.param("function"), Some("alice"));
+    assert_eq!(context.param_values("function"), ["bob", "alice"]);
+    assert_eq!(context.param("max-depth"), Some("2"));
+    assert_eq!(context.param("missing"), None);
+    assert!(context.param_values("missing").is_empty());
+}
+
+#[test]
+fn line_ranges_read_from_env() {
+    let context = read_context(&[
+        ("ANNO_TARGET", "/tmp/example.c"),
+        ("ANNO_TARGET_LINES", "12"),
+        ("ANNO_LINE_RANGES", "3-6,9-9"),
+    ]);
+    assert_eq!(context.line_ranges, Some(vec![3..=6, 9..=9]));
+    assert!(context.wants_line(4));
+    assert!(context.wants_line(9));
+    assert!(!context.wants_line(8));
+}
+
+#[test]
+fn target_name_read_from_env() {
+    let context = read_context(&[
+        ("ANNO_TARGET", "/tmp/anno-1234/buffer.c"),
+        ("ANNO_TARGET_LINES", "1"),
+        ("ANNO_TARGET_NAME", "/home/user/src/buffer.c"),
+    ]);
+    assert_eq!(context.target.to_str(), Some("/tmp/anno-1234/buffer.c"));
+    assert_eq!(
+        context.target_name.to_str(),
+        Some("/home/user/src/buffer.c")
+    );
+
+    let context = read_context(&[("ANNO_TARGET", "/src/a.c"), ("ANNO_TARGET_LINES", "1")]);
+    assert_eq!(context.target_name, context.target);
+}
+
+#[test]
+fn targets_read_from_file() {
+    let targets = env::temp_dir().join("anno-producer-targets.json");
+    fs::write(
+        &targets,
+        r#"[{"path":"/src/a.c","lines":3,"line_ranges":null},{"path":"/src/b.c","lines":5,"line_ranges":"2-4"}]"#,
+    )
+    .unwrap();
+    let context = read_context(&[
+        ("ANNO_TARGET", "/src/a.c"),
+        ("ANNO_TARGET_LINES", "3"),
+        ("ANNO_TARGETS", targets.to_str().unwrap()),
+    ]);
+    assert_eq!(
+        context.targets,
+        [
+            Target {
+                path: PathBuf::from("/src/a.c"),
+                line_count: 3,
+                line_ranges: None,
+            },
+            Target {
+                path: PathBuf::from("/src/b.c"),
+                line_count: 5,
+                line_ranges: Some(vec![2..=4]),
+            },
+        ]
+    );
+    let second = context.for_target(&context.targets[1]);
+    assert_eq!(second.target, PathBuf::from("/src/b.c"));
+    assert_eq!(second.line_count, 5);
+    assert!(!second.wants_line(1));
+    assert!(second.targets.is_empty());
+}
+
+#[test]
+fn missing_target_reported() {
+    let error = ProducerContext::from_vars(|_| None).unwrap_err();
+    assert!(error.to_string().contains("`ANNO_TARGET` is not set"));
+}
@@ -0,0 +1,117 @@
+use std::path::PathBuf;
+
+use anno_producer::{Output, Position, Protocol, Range};
+
+fn written(output: Output) -> String {
+    let mut data = Vec::new();
+    output.write(&mut data).unwrap();
+    String::from_utf8(data).unwrap()
+}
+
+fn range(start: (usize, usize), end: (usize, usize)) -> Range {
+    Range {
+        start: Position {
+            line: start.0,
+            column: Some(start.1),
+        },
+        end: Position {
+            line: end.0,
+            column: Some(end.1),
+        },
+    }
+}
+
+#[test]
+fn protocol_negotiated() {
+    assert_eq!(Protocol::negotiate("jsonl/1,lines"), Protocol::JsonLines);
+    assert_eq!(Protocol::negotiate("jsonl/2,lines"), Protocol::Lines);
+    assert_eq!(Protocol::negotiate(""), Protocol::Lines);
+}
+
+#[test]
+fn lines_protocol() {
+    let mut output = Output::with_protocol(Protocol::Lines, 4);
+    output.mark_lines([2, 4, 7], "x");
+    output.add_list(4, ["a", "b", "a"]);
+    output.add_range(range((1, 3), (2, 5)), "r");
+    assert_eq!(written(output), "r\nx r\n \nx a b\n");
+}
+
+#[test]
+fn json_lines_protocol() {
+    let mut output = Output::with_protocol(Protocol::JsonLines, 4);
+    output.mark_lines([2, 4], "x");
+    output.add_range(range((1, 3), (2, 5)), "r");
+    assert_eq!(
+        written(output),
+        concat!(
+            r#"{"protocol":"jsonl","version":1}"#,
+            "\n",
+            r#"{"line":2,"value":"x"}"#,
+            "\n",
+            r#"{"line":4,"value":"x"}"#,
+            "\n",
+            r#"{"range":{"end":{"column":5,"line":2},"start":{"column":3,"line":1}},"value":"r"}"#,
+            "\n",
+        )
+    );
+}
+
+#[test]
+fn json_lines_ranges_clipped() {
+    let mut output = Output::with_protocol(Protocol::JsonLines, 3);
+    output.add_range(range((2, 3), (5, 1)), "r");
+    output.add_range(range((4, 1), (6, 1)), "beyond");
+    assert_eq!(
+        written(output),
+        concat!(
+            r#"{"protocol":"jsonl","version":1}"#,
+            "\n",
+            r#"{"range":{"end":{"line":3},"start":{"column":3,"line":2}},"value":"r"}"#,
+            "\n",
+        )
+    );
+}
+
+#[test]
+fn json_lines_sets() {
+    let mut output = Output::with_protocol(Protocol::JsonLines, 2);
+    output.use_sets();
+    output.add_list(1, ["b", "a"]);
+    output.add_range(range((2, 1), (2, 4)), "c");
+    assert_eq!(
+        written(output),
+        concat!(
+            r#"{"protocol":"jsonl","version":1}"#,
+            "\n",
+            r#"{"line":1,"value":["b","a"]}"#,
+            "\n",
+            r#"{"range":{"end":{"column":4,"line":2},"start":{"column":1,"line":2}},"value":["c"]}"#,
+            "\n",
+        )
+    );
+}
+
+#[test]
+fn several_targets_written_together() {
+    let mut first = Output::with_protocol(Protocol::JsonLines, 2);
+    first.add(2, "x");
+    let mut second = Output::with_protocol(Protocol::JsonLines, 1);
+    second.add(1, "y");
+    let mut data = Vec::new();
+    Output::write_targets(
+        &[
+            (PathBuf::from("/src/a.c"), first),
+            (PathBuf::from("/src/b.c"), second),
+        ],
+        &mut data,
+    )
+    .unwrap();
+    assert_eq!(
+        String::from_utf8(data).unwrap(),
+        r#"{"protocol":"jsonl","targets":true,"version":1}
+{"line":2,"target":"/src/a.c","value":"x"}
+{"line":1,"target":"/src/b.c","value":"y"}
+"#
+    );
+}
@@ -0,0 +1,211 @@
+use std::{cmp::max, ops::RangeInclusive};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{describe::Capabilities, width};
+
+/// Annotation attached to a single line of the file being annotated
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Annotation {
+    /// Short value displayed in the producer's column
+    pub value: String,
+    /// Individual elements of the value when the producer supplied a set of
+    /// tokens (such as variable names), which are compared regardless of
+    /// order when diffing
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub elements: Option<Vec<String>>,
+    /// Longer explanation of the value, if the producer supplied one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    /// Importance of the annotation, if the producer supplied one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub severity: Option<Severity>,
+    /// Columns of the line the annotation applies to, if the producer
+    /// supplied a range narrower than the whole line
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub spans: Vec<Span>,
+}
+
+impl Annotation {
+    /// Merges another annotation for the same line into this one
+    ///
+    /// Values are joined with spaces, skipping any that are already present.
+    /// If either annotation is a set, the result is the union of both.
+    pub fn merge(&mut self, other: Annotation) {
+        if self.elements.is_some() || other.elements.is_some() {
+            let mut elements: Vec<String> = self.tokens().map(String::from).collect();
+            for token in other.tokens() {
+                if !elements.iter().any(|element| element == token) {
+                    elements.push(token.to_string());
+                }
+            }
+            self.value = elements.join(" ");
+            self.elements = Some(elements);
+        } else if self.value.is_empty() {
+            self.value = other.value;
+        } else if !other.value.is_empty()
+            && !self.value.split(' ').any(|value| value == other.value)
+        {
+            self.value.push(' ');
+            self.value.push_str(&other.value);
+        }
+        self.detail = match (self.detail.take(), other.detail) {
+            (Some(detail), Some(other_detail)) => Some(format!("{}\n{}", detail, other_detail)),
+            (detail, other_detail) => detail.or(other_detail),
+        };
+        self.severity = max(self.severity, other.severity);
+        for span in other.spans {
+            if !self.spans.contains(&span) {
+                self.spans.push(span);
+            }
+        }
+    }
+
+    /// Annotation with a set of tokens as its value
+    pub fn set(elements: Vec<String>) -> Annotation {
+        let mut unique: Vec<String> = Vec::new();
+        for element in elements {
+            if !unique.contains(&element) {
+                unique.push(element);
+            }
+        }
+        Annotation {
+            value: unique.join(" "),
+            elements: Some(unique),
+            ..Default::default()
+        }
+    }
+
+    /// Elements of a set, or else the whitespace-separated parts of the value
+    pub fn tokens(&self) -> Box<dyn Iterator<Item = &str> + '_> {
+        match &self.elements {
+            Some(elements) => Box::new(elements.iter().map(String::as_str)),
+            None => Box::new(self.value.split_whitespace()),
+        }
+    }
+}
+
+/// Position within the file being annotated
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Position {
+    /// Line number (1-based)
+    pub line: usize,
+    /// Column number (1-based), if not referring to the whole line
+    ///
+    /// Columns count UTF-8 bytes from the start of the line, as compilers
+    /// and debug info do, rather than characters.
+    pub column: Option<usize>,
+}
+
+/// Region of the file being annotated, including both `start` and `end`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Range {
+    pub start: Position,
+    pub end: Position,
+}
+
+impl Range {
+    /// Whether the range ends no earlier than it starts, with any columns
+    /// given starting from 1
+    pub fn is_valid(&self) -> bool {
+        let columns = [self.start.column, self.end.column];
+        let ordered = match columns {
+            [Some(start), Some(end)] if self.start.line == self.end.line => start <= end,
+            _ => self.start.line <= self.end.line,
+        };
+        ordered && columns.into_iter().flatten().all(|column| column >= 1)
+    }
+
+    /// Lines covered by this range
+    pub fn lines(&self) -> RangeInclusive<usize> {
+        self.start.line..=self.end.line
+    }
+
+    /// Columns covered by this range on the given line, or `None` when the
+    /// range has no columns and so applies to whole lines
+    pub fn span(&self, line: usize) -> Option<Span> {
+        if self.start.column.is_none() && self.end.column.is_none() {
+            return None;
+        }
+        let start = if line == self.start.line {
+            self.start.column.unwrap_or(1)
+        } else {
+            1
+        };
+        let end = if line == self.end.line {
+            self.end.column
+        } else {
+            None
+        };
+        Some(Span { start, end })
+    }
+}
+
+/// Columns (1-based byte offsets, inclusive) covered on a single line
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct Span {
+    pub start: usize,
+    /// Last column covered, or `None` to continue to the end of the line
+    pub end: Option<usize>,
+}
+
+impl Span {
+    pub fn contains(&self, column: usize) -> bool {
+        column >= self.start && self.end.is_none_or(|end| column <= end)
+    }
+
+    /// Whether any byte of `c`, starting at byte `index` (0-based) of the
+    /// line, is covered
+    pub fn covers(&self, index: usize, c: char) -> bool {
+        (index + 1..=index + c.len_utf8()).any(|column| self.contains(column))
+    }
+}
+
+/// Importance of an annotation
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+/// Annotations from a single producer for every line of a file
+#[derive(Debug)]
+pub struct Annotations {
+    /// Annotation for each line of the file being annotated
+    pub lines: Vec<Annotation>,
+    /// Maximum width of annotation values across all lines
+    pub max_width: usize,
+    /// Cap on the column width, which `max_width` never exceeds
+    pub width_cap: usize,
+    /// How the annotations should be compared and displayed, as declared by
+    /// the producer
+    pub capabilities: Capabilities,
+}
+
+/// Default cap on column width, to avoid huge columns
+pub const DEFAULT_MAX_WIDTH: usize = 30;
+
+impl Annotations {
+    pub fn new(lines: Vec<Annotation>) -> Annotations {
+        Annotations::with_max_width(lines, DEFAULT_MAX_WIDTH)
+    }
+
+    /// Collects annotations, capping the column width at `cap` terminal
+    /// columns
+    pub fn with_max_width(lines: Vec<Annotation>, cap: usize) -> Annotations {
+        let max_width = lines
+            .iter()
+            .fold(0, |acc, annotation| {
+                max(acc, width::display_width(&annotation.value))
+            })
+            .min(cap);
+        Annotations {
+            lines,
+            max_width,
+            width_cap: cap,
+            capabilities: Capabilities::default(),
+        }
+    }
+}
@@ -0,0 +1,163 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    env, fs,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result};
+use log::{debug, warn};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use walkdir::WalkDir;
+
+use crate::{protocol::SUPPORTED_PROTOCOLS, Producer};
+
+/// Stores producer output on disk, keyed by everything that may affect it,
+/// so producers need not run again when their inputs are unchanged
+///
+/// Producers are assumed to be deterministic. Keys cover the producer URI, the
+/// content of its executable and data source (or the latest modification time
+/// for a directory), the file being annotated, the environment variables Anno
+/// passes on, and the environment producers inherit from Anno (only the
+/// variables a producer says it reads, along with `PATH` and any
+/// `ANNO`-prefixed variables, when its description lists them).
+#[derive(Debug)]
+pub struct Cache {
+    dir: PathBuf,
+    /// Whether to ignore existing entries, while still storing new output
+    refresh: bool,
+    /// Digests of files already read
+    digests: Mutex<HashMap<FileVersion, String>>,
+}
+
+/// Identifies a version of a file by its path, modification time, and size
+type FileVersion = (PathBuf, Option<SystemTime>, u64);
+
+impl Cache {
+    /// Cache in `$XDG_CACHE_HOME/anno` (or `~/.cache/anno`), or `None` if
+    /// neither location is known
+    pub fn new(refresh: bool) -> Option<Cache> {
+        let cache_home = match env::var_os("XDG_CACHE_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => PathBuf::from(env::var_os("HOME")?).join(".cache"),
+        };
+        Some(Cache::in_dir(cache_home.join("anno"), refresh))
+    }
+
+    pub fn in_dir(dir: PathBuf, refresh: bool) -> Cache {
+        Cache {
+            dir,
+            refresh,
+            digests: Mutex::default(),
+        }
+    }
+
+    /// Key for output from `producer` given `inputs` describing the file
+    /// being annotated and the inherited variables it reads (or `None` if any
+    /// may matter), or `None` if the producer's executable cannot be found
+    pub(crate) fn key(
+        &self,
+        producer: &Producer,
+        reads_env: Option<&[String]>,
+        inputs: &Value,
+    ) -> Option<String> {
+        let executable = producer.executable()?;
+        let executable = self.file_digest(&executable)?;
+        let source = match Path::new(producer.source()) {
+            path if path.is_file() => self.file_digest(path),
+            path if path.is_dir() => Some(latest_modification(path)),
+            _ => None,
+        };
+        let inherited_env: BTreeMap<String, String> = env::vars_os()
+            .map(|(key, value)| {
+                (
+                    key.to_string_lossy().into_owned(),
+                    value.to_string_lossy().into_owned(),
+                )
+            })
+            .filter(|(key, _)| match reads_env {
+                Some(names) => key == "PATH" || key.starts_with("ANNO_") || names.contains(key),
+                None => true,
+            })
+            .collect();
+        let key = json!({
+            "anno": env!("CARGO_PKG_VERSION"),
+            "protocols": SUPPORTED_PROTOCOLS,
+            "producer": producer.uri(),
+            "executable": executable,
+            "source": source,
+            "inputs": inputs,
+            "inherited_env": inherited_env,
+        });
+        Some(digest(key.to_string().as_bytes()))
+    }
+
+    /// Key for the description an executable gives of itself, or `None` if
+    /// the executable cannot be read
+    pub(crate) fn description_key(&self, executable: &Path) -> Option<String> {
+        let key = json!({
+            "anno": env!("CARGO_PKG_VERSION"),
+            "describe": self.file_digest(executable)?,
+        });
+        Some(digest(key.to_string().as_bytes()))
+    }
+
+    /// Output stored for `key`, unless refreshing
+    pub(crate) fn get(&self, key: &str) -> Option<String> {
+        if self.refresh {
+            return None;
+        }
+        fs::read_to_string(self.dir.join(key)).ok()
+    }
+
+    /// Stores output for `key`, warning about (but otherwise ignoring) any
+    /// failure to do so
+    pub(crate) fn put(&self, key: &str, data: &str) {
+        if let Err(error) = self.write(key, data) {
+            warn!("Unable to cache producer output: {:#}", error);
+        }
+    }
+
+    fn write(&self, key: &str, data: &str) -> Result<()> {
+        fs::create_dir_all(&self.dir).with_context(|| {
+            format!("Unable to create cache directory ({})", self.dir.display())
+        })?;
+        // Written to a temporary file first so others never read part of it
+        let mut file = tempfile::NamedTempFile::new_in(&self.dir)?;
+        file.write_all(data.as_bytes())?;
+        file.persist(self.dir.join(key))?;
+        debug!("Cached output as {}", key);
+        Ok(())
+    }
+
+    fn file_digest(&self, path: &Path) -> Option<String> {
+        let metadata = fs::metadata(path).ok()?;
+        let id = (path.to_path_buf(), metadata.modified().ok(), metadata.len());
+        let mut digests = self.digests.lock().unwrap();
+        if let Some(digest) = digests.get(&id) {
+            return Some(digest.clone());
+        }
+        let digest = digest(&fs::read(path).ok()?);
+        digests.insert(id, digest.clone());
+        Some(digest)
+    }
+}
+
+/// Hex-encoded SHA-256 digest
+pub(crate) fn digest(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+/// Latest modification time (in nanoseconds) of anything within `dir`
+fn latest_modification(dir: &Path) -> String {
+    let latest = WalkDir::new(dir)
+        .into_iter()
+        .filter_map(|entry| entry.ok()?.metadata().ok()?.modified().ok())
+        .max()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .unwrap_or_default();
+    latest.as_nanos().to_string()
+}
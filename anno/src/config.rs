@@ -0,0 +1,222 @@
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context, Result};
+use log::debug;
+use path_absolutize::*;
+use serde::{de, Deserialize, Deserializer};
+
+use crate::{render::ColorChoice, Format, Producer};
+
+/// Name of configuration files searched for by Anno
+pub const CONFIG_FILE_NAME: &str = "anno.toml";
+
+/// Settings from `anno.toml` files
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Data source for each producer (by name) when none is given
+    pub sources: BTreeMap<String, String>,
+    /// Extra environment variables for every producer
+    pub env: BTreeMap<String, String>,
+    /// Display options used unless given on the command line
+    pub display: Display,
+    /// Named sets of producers and options, selected with `--preset`
+    pub presets: BTreeMap<String, Preset>,
+    /// Directories searched for `anno-<name>` executables before `PATH`
+    #[serde(rename = "producer-dirs")]
+    pub producer_dirs: Vec<PathBuf>,
+}
+
+/// Display options, each of which may be left unset
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Display {
+    pub diff: Option<bool>,
+    pub diff_only: Option<bool>,
+    pub diff_against: Option<usize>,
+    pub diff_columns: Option<Vec<usize>>,
+    pub context: Option<usize>,
+    pub max_width: Option<usize>,
+    /// Caps on the width of specific producers' columns (by name),
+    /// overriding `max-width`
+    pub column_widths: Option<BTreeMap<String, usize>>,
+    pub wrap: Option<bool>,
+    pub color: Option<ColorChoice>,
+    pub format: Option<Format>,
+    pub legend: Option<bool>,
+}
+
+/// Named set of producers and options
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Preset {
+    #[serde(deserialize_with = "deserialize_producers")]
+    pub producers: Vec<Producer>,
+    /// Extra environment variables, overriding those from the top level
+    pub env: BTreeMap<String, String>,
+    /// Display options, overriding those from the top level
+    pub display: Display,
+}
+
+fn deserialize_producers<'de, D>(deserializer: D) -> Result<Vec<Producer>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|spec| Producer::parse(spec).map_err(de::Error::custom))
+        .collect()
+}
+
+impl Config {
+    /// Reads a single configuration file
+    ///
+    /// Relative data sources are resolved against the directory containing
+    /// the file when they exist there, so the file applies in the same way
+    /// wherever Anno is run from.
+    pub fn load(path: &Path) -> Result<Config> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Unable to read config file ({})", path.display()))?;
+        let mut config: Config = toml::from_str(&content)
+            .with_context(|| format!("Unable to parse config file ({})", path.display()))?;
+
+        let dir = path.parent().unwrap_or(Path::new(""));
+        for producer_dir in &mut config.producer_dirs {
+            *producer_dir = dir.join(&*producer_dir);
+        }
+        for source in config.sources.values_mut() {
+            *source = resolve_source(dir, source);
+        }
+        for preset in config.presets.values_mut() {
+            preset.producers = preset
+                .producers
+                .drain(..)
+                .map(|producer| {
+                    let source = resolve_source(dir, producer.source());
+                    producer.with_source(source)
+                })
+                .collect();
+        }
+        Ok(config)
+    }
+
+    /// Combines the user's configuration (from `$XDG_CONFIG_HOME/anno`) with
+    /// the nearest `anno.toml` in or above the directory containing `target`,
+    /// with the latter taking precedence
+    pub fn discover(target: &Path) -> Result<Config> {
+        let target_path = target.absolutize()?;
+        Config::discover_in(target_path.parent().unwrap_or(Path::new("/")))
+    }
+
+    /// Combines the user's configuration with the nearest `anno.toml` in or
+    /// above `dir`, with the latter taking precedence
+    pub fn discover_in(dir: &Path) -> Result<Config> {
+        let mut config = Config::default();
+        if let Some(path) = user_config_path().filter(|path| path.is_file()) {
+            debug!("User config: {}", path.display());
+            config = Config::load(&path)?;
+        }
+        if let Some(path) = project_config_path(dir)? {
+            debug!("Project config: {}", path.display());
+            config = config.overridden_by(Config::load(&path)?);
+        }
+        Ok(config)
+    }
+
+    /// Merges in settings from `other`, which take precedence
+    pub fn overridden_by(mut self, other: Config) -> Config {
+        self.sources.extend(other.sources);
+        self.env.extend(other.env);
+        self.display = other.display.or(self.display);
+        self.presets.extend(other.presets);
+        // Searched in order, so directories from `other` come first
+        let mut producer_dirs = other.producer_dirs;
+        producer_dirs.extend(self.producer_dirs);
+        self.producer_dirs = producer_dirs;
+        self
+    }
+
+    /// Executable for the producer `name` in one of the producer directories,
+    /// if any has one
+    pub fn producer_command(&self, name: &str) -> Option<PathBuf> {
+        self.producer_dirs
+            .iter()
+            .map(|dir| dir.join(format!("anno-{}", name)))
+            .find(|path| path.is_file())
+    }
+
+    /// Looks up a preset by name
+    pub fn preset(&self, name: &str) -> Result<&Preset> {
+        self.presets.get(name).ok_or_else(|| {
+            let available: Vec<&str> = self.presets.keys().map(String::as_str).collect();
+            if available.is_empty() {
+                anyhow!(
+                    "No preset named `{}` (no presets are defined in any `{}`)",
+                    name,
+                    CONFIG_FILE_NAME
+                )
+            } else {
+                anyhow!(
+                    "No preset named `{}` (available: {})",
+                    name,
+                    available.join(", ")
+                )
+            }
+        })
+    }
+}
+
+impl Display {
+    /// Uses options from `self`, falling back to `other` for any unset
+    pub fn or(self, other: Display) -> Display {
+        Display {
+            diff: self.diff.or(other.diff),
+            diff_only: self.diff_only.or(other.diff_only),
+            diff_against: self.diff_against.or(other.diff_against),
+            diff_columns: self.diff_columns.or(other.diff_columns),
+            context: self.context.or(other.context),
+            max_width: self.max_width.or(other.max_width),
+            column_widths: self.column_widths.or(other.column_widths),
+            wrap: self.wrap.or(other.wrap),
+            color: self.color.or(other.color),
+            format: self.format.or(other.format),
+            legend: self.legend.or(other.legend),
+        }
+    }
+}
+
+fn user_config_path() -> Option<PathBuf> {
+    let config_home = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+    Some(config_home.join("anno").join(CONFIG_FILE_NAME))
+}
+
+fn project_config_path(dir: &Path) -> Result<Option<PathBuf>> {
+    let dir = dir.absolutize()?;
+    for dir in dir.ancestors() {
+        let path = dir.join(CONFIG_FILE_NAME);
+        if path.is_file() {
+            return Ok(Some(path));
+        }
+    }
+    Ok(None)
+}
+
+/// Resolves a relative data source against `dir` if it names a path there
+fn resolve_source(dir: &Path, source: &str) -> String {
+    if source.is_empty() || Path::new(source).is_absolute() {
+        return source.to_string();
+    }
+    let resolved = dir.join(source);
+    if resolved.exists() {
+        resolved.to_string_lossy().into_owned()
+    } else {
+        source.to_string()
+    }
+}
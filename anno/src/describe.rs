@@ -0,0 +1,405 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    env, fs,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use duct::cmd;
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cache::Cache,
+    protocol::SUPPORTED_PROTOCOLS,
+    run::{kill_group, start_in_group, POLL_INTERVAL},
+    Producer,
+};
+
+/// Argument asking a producer to describe itself instead of annotating a file
+pub const DESCRIBE_ARG: &str = "--anno-describe";
+
+/// Variable set alongside [`DESCRIBE_ARG`], so that producers which ignore
+/// their arguments can still tell they are not annotating a file
+pub const DESCRIBE_VAR: &str = "ANNO_DESCRIBE";
+
+/// Version of the description format understood by Anno
+const DESCRIBE_VERSION: u32 = 1;
+
+/// How long producers may take to describe themselves before they are killed
+const DESCRIBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// What a producer reports about itself when run with `--anno-describe`
+///
+/// Producers print a single JSON object such as:
+///
+/// ```json
+/// {"describe": 1, "name": "debug-line-table",
+///  "description": "Marks lines present in the DWARF line table",
+///  "source": "required", "source_description": "Path to debug info",
+///  "params": [{"name": "function", "description": "...", "required": false}],
+///  "env": ["CC", "CFLAGS"],
+///  "protocols": ["jsonl/1", "lines"],
+///  "capabilities": {"kind": "boolean", "legend": {"x": "In line table"}}}
+/// ```
+///
+/// Producers printing anything else (such as those written before this
+/// handshake existed) are treated as undescribed, and their specs are not
+/// checked.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Description {
+    /// Version of the description format
+    pub describe: u32,
+    pub name: Option<String>,
+    /// Summary of what the producer annotates
+    pub description: Option<String>,
+    /// Whether the producer uses a data source
+    #[serde(default)]
+    pub source: SourceUse,
+    /// What the data source should be, such as a path to debug info
+    pub source_description: Option<String>,
+    /// Query parameters accepted, or `None` if the producer does not say (in
+    /// which case any are allowed)
+    pub params: Option<Vec<Param>>,
+    /// Inherited environment variables that affect the producer's output,
+    /// or `None` if the producer does not say (in which case any may)
+    pub env: Option<Vec<String>>,
+    /// Output protocols the producer can write, in order of preference
+    #[serde(default)]
+    pub protocols: Vec<String>,
+    /// How the producer's annotations should be compared and displayed
+    #[serde(default)]
+    pub capabilities: Capabilities,
+}
+
+/// How a producer's annotations should be compared and displayed, as declared
+/// in its description
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Capabilities {
+    /// Whether comparing annotations with those of other producers is
+    /// meaningful (it is not for line numbers, for example)
+    pub diffable: bool,
+    /// Kind of value in each annotation, if known
+    pub kind: Option<ValueKind>,
+    /// Preferred cap on the column width
+    pub width: Option<usize>,
+    /// Alignment of annotations within the column, if not the default for
+    /// their kind
+    pub align: Option<Align>,
+    /// Meaning of each symbol used in annotations, such as `x`
+    pub legend: BTreeMap<String, String>,
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Capabilities {
+            diffable: true,
+            kind: None,
+            width: None,
+            align: None,
+            legend: BTreeMap::new(),
+        }
+    }
+}
+
+impl Capabilities {
+    /// Alignment to use, which is right for numbers and otherwise left
+    pub fn alignment(&self) -> Align {
+        match (self.align, self.kind) {
+            (Some(align), _) => align,
+            (None, Some(ValueKind::Number)) => Align::Right,
+            (None, _) => Align::Left,
+        }
+    }
+}
+
+/// Kind of value in a producer's annotations
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ValueKind {
+    /// Marks (such as `x`) that are either present or blank, compared only by
+    /// whether they are present
+    Boolean,
+    /// Sets of tokens (such as variable names), compared regardless of order
+    Set,
+    Number,
+    Text,
+}
+
+/// Alignment of annotations within a column
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Align {
+    #[default]
+    Left,
+    Right,
+}
+
+/// Whether a producer needs a data source
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SourceUse {
+    Required,
+    #[default]
+    Optional,
+    None,
+}
+
+/// Query parameter accepted by a producer
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Param {
+    pub name: String,
+    pub description: Option<String>,
+    #[serde(default)]
+    pub required: bool,
+}
+
+impl Description {
+    /// Parses the output of `--anno-describe`, or returns `None` if it is not
+    /// a description Anno understands
+    pub fn parse(output: &str) -> Option<Description> {
+        let description: Description = serde_json::from_str(output.trim()).ok()?;
+        if description.describe != DESCRIBE_VERSION {
+            debug!("Unsupported description version {}", description.describe);
+            return None;
+        }
+        Some(description)
+    }
+
+    /// Checks that `producer` is given what this description asks for,
+    /// returning each problem found
+    pub fn problems(&self, producer: &Producer) -> Vec<String> {
+        let mut problems = Vec::new();
+        match self.source {
+            SourceUse::Required if producer.source().is_empty() => {
+                problems.push(match &self.source_description {
+                    Some(description) => format!("requires a data source ({})", description),
+                    None => "requires a data source".to_string(),
+                });
+            }
+            SourceUse::None if !producer.source().is_empty() => {
+                problems.push("does not take a data source".to_string());
+            }
+            _ => {}
+        }
+
+        if let Some(params) = &self.params {
+            let given = producer.params();
+            for name in given.keys() {
+                if params.iter().any(|param| &param.name == name) {
+                    continue;
+                }
+                let accepted: Vec<String> = params
+                    .iter()
+                    .map(|param| format!("`{}`", param.name))
+                    .collect();
+                problems.push(if accepted.is_empty() {
+                    format!("has no parameter `{}` (it accepts none)", name)
+                } else {
+                    format!(
+                        "has no parameter `{}` (accepted: {})",
+                        name,
+                        accepted.join(", ")
+                    )
+                });
+            }
+            for param in params.iter().filter(|param| param.required) {
+                if !given.contains_key(&param.name) {
+                    problems.push(format!("requires parameter `{}`", param.name));
+                }
+            }
+        }
+
+        let supported: Vec<&str> = SUPPORTED_PROTOCOLS.split(',').collect();
+        if !self.protocols.is_empty()
+            && !self
+                .protocols
+                .iter()
+                .any(|protocol| supported.contains(&protocol.as_str()))
+        {
+            problems.push(format!(
+                "writes only protocols this version of Anno does not support ({})",
+                self.protocols.join(", ")
+            ));
+        }
+        problems
+    }
+}
+
+/// Producer executable found by [`discover`]
+#[derive(Clone, Debug, Serialize)]
+pub struct Discovered {
+    /// Producer name, without the `anno-` prefix
+    pub name: String,
+    pub path: PathBuf,
+}
+
+/// Finds `anno-*` executables in `dirs` followed by each directory in `PATH`
+///
+/// When several directories contain a producer with the same name, the first
+/// is kept, as it is the one that would be run. Producers are sorted by name.
+pub fn discover(dirs: &[PathBuf]) -> Vec<Discovered> {
+    let path_dirs = env::var_os("PATH")
+        .map(|path| env::split_paths(&path).collect::<Vec<_>>())
+        .unwrap_or_default();
+    let mut names = BTreeSet::new();
+    let mut found = Vec::new();
+    for dir in dirs.iter().chain(&path_dirs) {
+        let Ok(entries) = fs::read_dir(dir) else {
+            continue;
+        };
+        let mut paths: Vec<PathBuf> = entries
+            .filter_map(|entry| Some(entry.ok()?.path()))
+            .collect();
+        paths.sort();
+        for path in paths {
+            let Some(name) = path
+                .file_name()
+                .and_then(|file_name| file_name.to_str()?.strip_prefix("anno-"))
+            else {
+                continue;
+            };
+            if name.is_empty() || !is_executable(&path) || !names.insert(name.to_string()) {
+                continue;
+            }
+            found.push(Discovered {
+                name: name.to_string(),
+                path,
+            });
+        }
+    }
+    found.sort_by(|a, b| a.name.cmp(&b.name));
+    found
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    fs::metadata(path)
+        .is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+/// Asks each executable to describe itself, running them all concurrently
+/// and returning descriptions in the same order
+///
+/// Descriptions are reused from `cache` when the executable is unchanged,
+/// including the lack of one from executables that fail or time out.
+pub fn describe(executables: &[PathBuf], cache: Option<&Cache>) -> Vec<Option<Description>> {
+    let keys: Vec<Option<String>> = executables
+        .iter()
+        .map(|executable| cache?.description_key(executable))
+        .collect();
+    let mut outputs: Vec<Option<String>> =
+        keys.iter().map(|key| cache?.get(key.as_ref()?)).collect();
+
+    // Launch every producer before waiting on any of them
+    let started = Instant::now();
+    let mut handles: Vec<_> = executables
+        .iter()
+        .zip(&outputs)
+        .map(|(executable, output)| {
+            if output.is_some() {
+                return None;
+            }
+            debug!("Describing `{}`", executable.display());
+            let command = cmd!(executable, DESCRIBE_ARG)
+                .env(DESCRIBE_VAR, "1")
+                .stdin_null()
+                .stderr_null()
+                .stdout_capture()
+                .unchecked();
+            start_in_group(command).ok()
+        })
+        .collect();
+    while handles.iter().any(Option::is_some) {
+        let expired = started.elapsed() >= DESCRIBE_TIMEOUT;
+        for (i, slot) in handles.iter_mut().enumerate() {
+            let Some(handle) = slot else {
+                continue;
+            };
+            match handle.try_wait() {
+                Ok(Some(output)) => {
+                    let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+                    outputs[i] = Some(if output.status.success() {
+                        stdout
+                    } else {
+                        String::new()
+                    });
+                }
+                Ok(None) if expired => {
+                    debug!("Killing `{}`", executables[i].display());
+                    kill_group(handle);
+                    outputs[i] = Some(String::new());
+                }
+                Ok(None) => continue,
+                Err(_) => outputs[i] = Some(String::new()),
+            }
+            // Failures and timeouts are cached too, so that producers which
+            // do not describe themselves are not slowed down on every run
+            if let (Some(cache), Some(key)) = (cache, &keys[i]) {
+                cache.put(key, outputs[i].as_ref().unwrap());
+            }
+            *slot = None;
+        }
+        if handles.iter().any(Option::is_some) {
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    outputs
+        .iter()
+        .map(|output| Description::parse(output.as_deref().unwrap_or_default()))
+        .collect()
+}
+
+/// Checks each producer against the description its executable gives,
+/// reporting every problem found together, and otherwise returns the
+/// description for each producer
+///
+/// Producers whose executable cannot be found, or which do not describe
+/// themselves, are not checked here. Any problem running them is reported
+/// when they are run instead.
+pub fn check_producers(
+    producers: &[Producer],
+    cache: Option<&Cache>,
+) -> Result<Vec<Option<Description>>> {
+    let executables: Vec<PathBuf> = producers
+        .iter()
+        .filter_map(Producer::executable)
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    let descriptions = describe(&executables, cache);
+    let mut problems = Vec::new();
+    let mut producer_descriptions = Vec::new();
+    for producer in producers {
+        let description = producer.executable().and_then(|executable| {
+            let index = executables.binary_search(&executable).unwrap();
+            descriptions[index].clone()
+        });
+        if let Some(description) = &description {
+            for problem in description.problems(producer) {
+                problems.push(format!("`{}` {}", producer.uri(), problem));
+            }
+        }
+        producer_descriptions.push(description);
+    }
+    match problems.len() {
+        0 => Ok(producer_descriptions),
+        1 => Err(anyhow!("Producer {}", problems[0])),
+        n => Err(anyhow!(
+            "{} problems with producers:\n  - {}",
+            n,
+            problems.join("\n  - ")
+        )),
+    }
+}
@@ -0,0 +1,52 @@
+//! Aggregates annotations for each line of a file from one or more annotation
+//! data producers.
+//!
+//! Producers are separate `anno-<name>` commands. A [`Session`] runs a set of
+//! producers over a file, collecting their output as [`Annotations`]. These
+//! are combined line by line into a [`Table`], which a [`Renderer`] can then
+//! write out in various formats.
+//!
+//! ```no_run
+//! # fn main() -> anyhow::Result<()> {
+//! use std::{io, path::Path};
+//!
+//! use anno::{DiffOptions, Format, Producer, Session};
+//!
+//! let session = Session::new(vec![Producer::parse("numbers:")?]);
+//! let annotated = session.annotate(Path::new("src/main.rs"))?;
+//! let table = annotated.table(DiffOptions::default());
+//! Format::Terminal
+//!     .renderer()
+//!     .render(&table, &mut io::stdout())?;
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod annotations;
+pub mod cache;
+pub mod config;
+pub mod describe;
+pub mod lsp;
+pub mod producer;
+pub mod protocol;
+pub mod render;
+pub mod run;
+pub mod selection;
+pub mod session;
+pub mod summary;
+pub mod table;
+pub mod targets;
+pub mod tui;
+pub mod validate;
+pub mod watch;
+pub mod width;
+
+pub use crate::{
+    annotations::{Annotation, Annotations},
+    config::Config,
+    producer::Producer,
+    render::{Format, Renderer},
+    session::{Annotated, Session},
+    summary::Summary,
+    table::{DiffOptions, Table},
+};
@@ -0,0 +1,426 @@
+use std::{collections::HashMap, path::Path};
+
+use anyhow::{anyhow, Result};
+use log::{debug, warn};
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, RequestId, Response};
+use lsp_types::{
+    notification::{
+        DidCloseTextDocument, DidOpenTextDocument, DidSaveTextDocument, Notification as _,
+        PublishDiagnostics, ShowMessage,
+    },
+    request::{
+        CodeLensRefresh, CodeLensRequest, HoverRequest, InlayHintRefreshRequest, InlayHintRequest,
+        Request as _,
+    },
+    CodeLens, CodeLensOptions, CodeLensParams, Command, Diagnostic, DiagnosticSeverity, Hover,
+    HoverContents, HoverParams, HoverProviderCapability, InitializeParams, InlayHint,
+    InlayHintLabel, InlayHintParams, InlayHintTooltip, MarkupContent, MarkupKind, MessageType,
+    OneOf, Position, PublishDiagnosticsParams, Range, ServerCapabilities, ShowMessageParams,
+    TextDocumentSyncCapability, TextDocumentSyncKind, TextDocumentSyncOptions,
+    TextDocumentSyncSaveOptions, Url,
+};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::{
+    annotations::{Annotation, Severity},
+    table::DiffOptions,
+    Session,
+};
+
+/// Features beyond inlay hints and hover text to offer editors
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LspOptions {
+    /// Publish annotations with a severity as diagnostics
+    pub diagnostics: bool,
+    /// Show a code lens summarising each producer at the top of each file
+    pub code_lens: bool,
+}
+
+/// Runs a Language Server Protocol server over stdio until the editor asks it
+/// to exit
+///
+/// Each file opened in the editor is annotated by the session returned by
+/// `session_for` (so settings can depend on the file), and again each time it
+/// is saved. Annotations are shown as inlay hints at the end of each line and
+/// as hover text.
+pub fn serve(session_for: impl Fn(&Path) -> Result<Session>, options: LspOptions) -> Result<()> {
+    let (connection, io_threads) = Connection::stdio();
+    let capabilities = ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Options(
+            TextDocumentSyncOptions {
+                open_close: Some(true),
+                change: Some(TextDocumentSyncKind::NONE),
+                save: Some(TextDocumentSyncSaveOptions::Supported(true)),
+                ..Default::default()
+            },
+        )),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        inlay_hint_provider: Some(OneOf::Left(true)),
+        code_lens_provider: options.code_lens.then_some(CodeLensOptions {
+            resolve_provider: Some(false),
+        }),
+        ..Default::default()
+    };
+    let params = connection.initialize(serde_json::to_value(capabilities)?)?;
+    let params: InitializeParams = serde_json::from_value(params)?;
+
+    let mut server = Server {
+        connection: &connection,
+        session_for,
+        options,
+        params,
+        documents: HashMap::new(),
+        next_request: 0,
+    };
+    server.run()?;
+    drop(connection);
+    io_threads.join()?;
+    Ok(())
+}
+
+/// Annotations for a file open in the editor
+struct Document {
+    /// Text of each line, as last annotated
+    lines: Vec<String>,
+    /// Non-blank annotations on each line, with the key of their column
+    annotations: Vec<Vec<(String, Annotation)>>,
+    /// Key of each column with the number of lines it annotates
+    columns: Vec<(String, usize)>,
+}
+
+struct Server<'c, F> {
+    connection: &'c Connection,
+    session_for: F,
+    options: LspOptions,
+    params: InitializeParams,
+    documents: HashMap<Url, Document>,
+    next_request: i32,
+}
+
+impl<F: Fn(&Path) -> Result<Session>> Server<'_, F> {
+    fn run(&mut self) -> Result<()> {
+        for message in &self.connection.receiver {
+            match message {
+                Message::Request(request) => {
+                    if self.connection.handle_shutdown(&request)? {
+                        return Ok(());
+                    }
+                    self.handle_request(request)?;
+                }
+                Message::Notification(notification) => {
+                    let method = notification.method.clone();
+                    if let Err(error) = self.handle_notification(notification) {
+                        warn!("Unable to handle `{}` notification: {:#}", method, error);
+                    }
+                }
+                // Replies to refresh requests need no action
+                Message::Response(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_request(&mut self, request: Request) -> Result<()> {
+        let id = request.id.clone();
+        let method = request.method.clone();
+        let response = match self.respond(request) {
+            Ok(Some(result)) => Response::new_ok(id, result),
+            Ok(None) => Response::new_err(
+                id,
+                ErrorCode::MethodNotFound as i32,
+                format!("Unsupported request `{}`", method),
+            ),
+            Err(error) => Response::new_err(id, ErrorCode::InvalidParams as i32, error.to_string()),
+        };
+        self.connection.sender.send(response.into())?;
+        Ok(())
+    }
+
+    /// Result for a request, or `None` if the request is not supported
+    fn respond(&self, request: Request) -> Result<Option<Value>> {
+        let result = match request.method.as_str() {
+            InlayHintRequest::METHOD => {
+                let params: InlayHintParams = parse_params(request)?;
+                serde_json::to_value(self.inlay_hints(&params.text_document.uri, params.range))?
+            }
+            HoverRequest::METHOD => {
+                let params: HoverParams = parse_params(request)?;
+                let position = params.text_document_position_params;
+                serde_json::to_value(self.hover(&position.text_document.uri, position.position))?
+            }
+            CodeLensRequest::METHOD => {
+                let params: CodeLensParams = parse_params(request)?;
+                serde_json::to_value(self.code_lenses(&params.text_document.uri))?
+            }
+            _ => return Ok(None),
+        };
+        Ok(Some(result))
+    }
+
+    fn handle_notification(&mut self, notification: Notification) -> Result<()> {
+        match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let params: lsp_types::DidOpenTextDocumentParams =
+                    serde_json::from_value(notification.params)?;
+                self.annotate(params.text_document.uri)?;
+            }
+            DidSaveTextDocument::METHOD => {
+                let params: lsp_types::DidSaveTextDocumentParams =
+                    serde_json::from_value(notification.params)?;
+                self.annotate(params.text_document.uri)?;
+                self.refresh()?;
+            }
+            DidCloseTextDocument::METHOD => {
+                let params: lsp_types::DidCloseTextDocumentParams =
+                    serde_json::from_value(notification.params)?;
+                self.documents.remove(&params.text_document.uri);
+                if self.options.diagnostics {
+                    self.publish_diagnostics(params.text_document.uri, Vec::new())?;
+                }
+            }
+            method => debug!("Ignoring notification `{}`", method),
+        }
+        Ok(())
+    }
+
+    /// Runs producers over the saved copy of the file, reporting any failure
+    /// to the editor (while keeping earlier annotations)
+    fn annotate(&mut self, uri: Url) -> Result<()> {
+        let document = uri
+            .to_file_path()
+            .map_err(|_| anyhow!("Only files can be annotated"))
+            .and_then(|path| self.annotate_path(&path));
+        match document {
+            Ok(document) => {
+                if self.options.diagnostics {
+                    let diagnostics = diagnostics(&document);
+                    self.publish_diagnostics(uri.clone(), diagnostics)?;
+                }
+                self.documents.insert(uri, document);
+            }
+            Err(error) => {
+                warn!("Unable to annotate {}: {:#}", uri, error);
+                let params = ShowMessageParams {
+                    typ: MessageType::ERROR,
+                    message: format!("Unable to annotate {}: {:#}", uri, error),
+                };
+                self.notify::<ShowMessage>(params)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn annotate_path(&self, path: &Path) -> Result<Document> {
+        let session = (self.session_for)(path)?;
+        let annotated = session.annotate(path)?;
+        let table = annotated.table(DiffOptions::default());
+        let mut annotations = vec![Vec::new(); table.line_count];
+        for row in &table.rows {
+            annotations[row.number - 1] = table
+                .columns
+                .iter()
+                .zip(&row.annotations)
+                .filter(|(_, annotation)| !annotation.value.trim().is_empty())
+                .map(|(column, &annotation)| (column.key.clone(), annotation.clone()))
+                .collect();
+        }
+        let columns = table
+            .columns
+            .iter()
+            .map(|column| {
+                let count = annotations
+                    .iter()
+                    .filter(|line| line.iter().any(|(key, _)| key == &column.key))
+                    .count();
+                (column.key.clone(), count)
+            })
+            .collect();
+        Ok(Document {
+            lines: annotated.content.lines().map(String::from).collect(),
+            annotations,
+            columns,
+        })
+    }
+
+    /// Asks the editor to fetch inlay hints and code lenses again, if it
+    /// supports doing so
+    fn refresh(&mut self) -> Result<()> {
+        let workspace = self.params.capabilities.workspace.as_ref();
+        let inlay_hints = workspace
+            .and_then(|workspace| workspace.inlay_hint.as_ref())
+            .and_then(|inlay_hint| inlay_hint.refresh_support)
+            .unwrap_or_default();
+        let code_lenses = workspace
+            .and_then(|workspace| workspace.code_lens.as_ref())
+            .and_then(|code_lens| code_lens.refresh_support)
+            .unwrap_or_default();
+        if inlay_hints {
+            self.request(InlayHintRefreshRequest::METHOD)?;
+        }
+        if self.options.code_lens && code_lenses {
+            self.request(CodeLensRefresh::METHOD)?;
+        }
+        Ok(())
+    }
+
+    fn inlay_hints(&self, uri: &Url, range: Range) -> Vec<InlayHint> {
+        let Some(document) = self.documents.get(uri) else {
+            return Vec::new();
+        };
+        let mut hints = Vec::new();
+        for (index, annotations) in document.annotations.iter().enumerate() {
+            let line = index as u32;
+            if line < range.start.line || line > range.end.line {
+                continue;
+            }
+            let end = document.lines[index].encode_utf16().count() as u32;
+            for (key, annotation) in annotations {
+                hints.push(InlayHint {
+                    position: Position::new(line, end),
+                    label: InlayHintLabel::String(format!("{}: {}", key, annotation.value)),
+                    kind: None,
+                    text_edits: None,
+                    tooltip: annotation.detail.clone().map(InlayHintTooltip::String),
+                    padding_left: Some(true),
+                    padding_right: None,
+                    data: None,
+                });
+            }
+        }
+        hints
+    }
+
+    fn hover(&self, uri: &Url, position: Position) -> Option<Hover> {
+        let document = self.documents.get(uri)?;
+        let annotations = document.annotations.get(position.line as usize)?;
+        if annotations.is_empty() {
+            return None;
+        }
+        let value = annotations
+            .iter()
+            .map(|(key, annotation)| {
+                let mut text = format!("**{}**: `{}`", key, annotation.value);
+                if let Some(detail) = &annotation.detail {
+                    text.push_str("\n\n");
+                    text.push_str(detail);
+                }
+                text
+            })
+            .collect::<Vec<_>>()
+            .join("\n\n---\n\n");
+        Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value,
+            }),
+            range: None,
+        })
+    }
+
+    fn code_lenses(&self, uri: &Url) -> Vec<CodeLens> {
+        let Some(document) = self.documents.get(uri) else {
+            return Vec::new();
+        };
+        document
+            .columns
+            .iter()
+            .map(|(key, count)| CodeLens {
+                range: Range::default(),
+                command: Some(Command {
+                    title: format!("{}: {} of {} lines", key, count, document.lines.len()),
+                    command: String::new(),
+                    arguments: None,
+                }),
+                data: None,
+            })
+            .collect()
+    }
+
+    fn publish_diagnostics(&self, uri: Url, diagnostics: Vec<Diagnostic>) -> Result<()> {
+        self.notify::<PublishDiagnostics>(PublishDiagnosticsParams {
+            uri,
+            diagnostics,
+            version: None,
+        })
+    }
+
+    fn notify<N: lsp_types::notification::Notification>(&self, params: N::Params) -> Result<()> {
+        let notification = Notification::new(N::METHOD.to_string(), params);
+        self.connection.sender.send(notification.into())?;
+        Ok(())
+    }
+
+    fn request(&mut self, method: &str) -> Result<()> {
+        self.next_request += 1;
+        let id = RequestId::from(format!("anno-{}", self.next_request));
+        let request = Request::new(id, method.to_string(), ());
+        self.connection.sender.send(request.into())?;
+        Ok(())
+    }
+}
+
+/// Annotations with a severity, covering their spans (or else the whole line)
+fn diagnostics(document: &Document) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    for (index, annotations) in document.annotations.iter().enumerate() {
+        let text = &document.lines[index];
+        for (key, annotation) in annotations {
+            let Some(severity) = annotation.severity else {
+                continue;
+            };
+            let line = index as u32;
+            let mut ranges: Vec<Range> = annotation
+                .spans
+                .iter()
+                .map(|span| {
+                    // Span columns are 1-based and inclusive byte offsets
+                    let start = utf16_column(text, span.start - 1);
+                    let end = span.end.map_or(utf16_column(text, usize::MAX), |end| {
+                        utf16_column(text, end)
+                    });
+                    Range::new(Position::new(line, start), Position::new(line, end))
+                })
+                .collect();
+            if ranges.is_empty() {
+                ranges.push(Range::new(
+                    Position::new(line, 0),
+                    Position::new(line, utf16_column(text, usize::MAX)),
+                ));
+            }
+            let mut message = format!("{}: {}", key, annotation.value);
+            if let Some(detail) = &annotation.detail {
+                message.push('\n');
+                message.push_str(detail);
+            }
+            for range in ranges {
+                diagnostics.push(Diagnostic {
+                    range,
+                    severity: Some(match severity {
+                        Severity::Info => DiagnosticSeverity::INFORMATION,
+                        Severity::Warning => DiagnosticSeverity::WARNING,
+                        Severity::Error => DiagnosticSeverity::ERROR,
+                    }),
+                    source: Some("anno".to_string()),
+                    message: message.clone(),
+                    ..Default::default()
+                });
+            }
+        }
+    }
+    diagnostics
+}
+
+/// Converts a number of UTF-8 bytes from the start of `text` into UTF-16 code
+/// units, as used by LSP positions, including any character cut part way
+fn utf16_column(text: &str, bytes: usize) -> u32 {
+    text.char_indices()
+        .take_while(|&(i, _)| i < bytes)
+        .map(|(_, c)| c.len_utf16())
+        .sum::<usize>() as u32
+}
+
+fn parse_params<P: DeserializeOwned>(request: Request) -> Result<P> {
+    serde_json::from_value(request.params)
+        .map_err(|error| anyhow!("Invalid `{}` request: {}", request.method, error))
+}
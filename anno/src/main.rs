@@ -1,54 +1,305 @@
 use std::{
-    cmp::max,
-    fs,
-    iter::Peekable,
-    path::PathBuf,
-    str::{FromStr, Lines},
+    env, fs,
+    io::{self, IsTerminal, Read, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
 };
 
-use anyhow::{Context, Error, Ok, Result};
-use clap::Parser;
-use duct::cmd;
+use anno::{
+    cache::Cache,
+    config::Display,
+    describe::{describe, discover, Description, SourceUse, ValueKind},
+    lsp::{self, LspOptions},
+    render::{ColorChoice, TerminalRenderer},
+    run::Timeouts,
+    selection::Selection,
+    targets::{expand_targets, is_pattern},
+    validate::MismatchPolicy,
+    watch, Annotated, Config, DiffOptions, Format, Producer, Session, Summary, Table,
+};
+use anyhow::{anyhow, Context, Error, Ok, Result};
+use clap::{Parser, Subcommand};
 use log::debug;
-use path_absolutize::*;
-use url::Url;
-use yansi::{Color, Paint};
+use serde_json::json;
 
 #[derive(Parser, Debug)]
-#[command(version, about)]
+#[command(version, about, args_conflicts_with_subcommands = true)]
 struct Cli {
-    /// File to annotate
-    file: PathBuf,
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Files or directories to annotate, or `-` to read a file from stdin
+    ///
+    /// Directories are searched recursively for text files, leaving out
+    /// hidden ones. Glob patterns such as `'src/**/*.c'` are also expanded.
+    /// When annotating several files, each is shown in its own section, and
+    /// JSON and HTML reports start with an index summarising each file.
+    #[arg(required = true, value_name = "FILE")]
+    files: Vec<PathBuf>,
+
+    /// Path of the file being annotated as shown and given to producers,
+    /// when its content comes from stdin or another file [default: stdin]
+    ///
+    /// Producers read the content from a temporary file with the same name,
+    /// and are given this path in `ANNO_TARGET_NAME`. Useful for annotating
+    /// unsaved editor buffers.
+    #[arg(long, value_name = "PATH")]
+    target_name: Option<PathBuf>,
 
     /// Add an annotation data producer URI
     ///
     /// Several different URI formats are accepted, for example:
     ///
     /// - Producer only
-    ///   `producer:`
+    ///   `producer`
     /// - Producer with data source
     ///   `producer:/path/to/data/source`
     /// - Producer with data source and additional arguments
     ///   `producer:/path/to/data/source?param=value`
-    #[arg(short, long = "producer", id = "PRODUCER", verbatim_doc_comment)]
+    /// - Producer executable at a specific path
+    ///   `./path/to/anno-producer:/path/to/data/source`
+    #[arg(
+        short,
+        long = "producer",
+        id = "PRODUCER",
+        verbatim_doc_comment,
+        global = true
+    )]
     producers: Vec<Producer>,
 
-    /// Highlight differences in annotations between the first two producers
-    #[arg(long)]
+    /// Add the producers and options from a preset defined in `anno.toml`
+    ///
+    /// Options given on the command line take precedence over the preset.
+    #[arg(long, value_name = "NAME", global = true)]
+    preset: Option<String>,
+
+    /// Run the named producer from this executable instead of searching
+    /// `PATH` for `anno-<PRODUCER>`
+    ///
+    /// May be given multiple times.
+    #[arg(long = "producer-path", value_name = "PRODUCER=PATH", global = true)]
+    producer_paths: Vec<ProducerPath>,
+
+    /// Only show these lines, e.g. `120-180` or `42`
+    ///
+    /// May be given multiple times or as a comma-separated list.
+    #[arg(
+        long,
+        value_name = "RANGES",
+        value_delimiter = ',',
+        value_parser = Selection::parse_lines
+    )]
+    lines: Vec<Selection>,
+
+    /// Only show the definition of this C function
+    ///
+    /// Functions are found using a lightweight heuristic based on braces. May
+    /// be given multiple times.
+    #[arg(long, value_name = "NAME")]
+    function: Vec<String>,
+
+    /// Highlight differences in annotations from the baseline producer
+    #[arg(long, overrides_with = "no_diff")]
     diff: bool,
 
-    /// Only show lines with differences between the first two producers
-    // TODO: Treat `lines` as special built-in that doesn't get diffed...?
-    // Or somehow have producers report if they can be diffed
-    #[arg(long)]
+    /// Do not highlight differences, even if `anno.toml` or the preset asks
+    /// for it
+    #[arg(long, overrides_with = "diff")]
+    no_diff: bool,
+
+    /// Only show lines with differences from the baseline producer
+    #[arg(long, overrides_with = "no_diff_only")]
     diff_only: bool,
 
+    /// Show all lines, even if `anno.toml` or the preset asks for only those
+    /// with differences
+    #[arg(long, overrides_with = "diff_only")]
+    no_diff_only: bool,
+
+    /// Producer column (numbered from 0) to compare others against in diff
+    /// mode [default: first producer that can be diffed]
+    ///
+    /// Implies `--diff` unless `--diff-only` is given.
+    #[arg(long, value_name = "COLUMN")]
+    diff_against: Option<usize>,
+
+    /// Producer columns (numbered from 0) to compare against the baseline in
+    /// diff mode [default: all others that can be diffed]
+    ///
+    /// Producers can declare that their columns cannot be meaningfully diffed
+    /// (as `numbers` does), leaving them out unless selected here. Implies
+    /// `--diff` unless `--diff-only` is given.
+    #[arg(long, value_name = "COLUMNS", value_delimiter = ',')]
+    diff_columns: Option<Vec<usize>>,
+
+    /// Show this many lines around each line with differences in diff only
+    /// mode [default: 0]
+    ///
+    /// Line numbers are shown alongside each line, with gaps between lines
+    /// marked by `...`.
+    #[arg(short = 'C', long, value_name = "LINES")]
+    context: Option<usize>,
+
+    /// Kill producers that run longer than this many seconds
+    ///
+    /// Use `SECONDS` to limit every producer or `PRODUCER=SECONDS` to limit a
+    /// specific producer. May be given multiple times.
+    #[arg(
+        long = "producer-timeout",
+        value_name = "[PRODUCER=]SECONDS",
+        global = true
+    )]
+    producer_timeouts: Vec<ProducerTimeout>,
+
+    /// Kill any producers still running after this many seconds in total
+    #[arg(long, value_name = "SECONDS", value_parser = parse_seconds, global = true)]
+    timeout: Option<Duration>,
+
+    /// What to do when a producer's output has the wrong number of lines
+    #[arg(
+        long,
+        value_enum,
+        value_name = "POLICY",
+        default_value_t,
+        global = true
+    )]
+    on_mismatch: MismatchPolicy,
+
+    /// Print statistics comparing producers instead of the annotated file
+    ///
+    /// Counts the lines and elements annotated by each producer, then compares
+    /// each producer selected by `--diff-columns` with the `--diff-against`
+    /// baseline. Only pairs including the baseline are compared, so choose a
+    /// different baseline to compare two other producers. Written as text, or
+    /// as JSON with `--format json`.
+    #[arg(long)]
+    summary: bool,
+
+    /// Browse annotations interactively in the terminal
+    ///
+    /// Offers scrolling, searching, jumping between differences, hiding
+    /// columns, showing annotations in full, and re-running producers.
+    #[arg(long, conflicts_with_all = ["summary", "format"])]
+    tui: bool,
+
+    /// Keep running, annotating the file again whenever it or a producer's
+    /// data source changes
+    ///
+    /// Only producers whose data sources changed are run again, unless the
+    /// file itself changed. Output is redrawn once changes settle.
+    #[arg(long, conflicts_with_all = ["tui", "target_name"])]
+    watch: bool,
+
+    /// Run producers again instead of reusing their cached output, storing
+    /// the new output in the cache
+    ///
+    /// Producer output is cached in `$XDG_CACHE_HOME/anno` (or
+    /// `~/.cache/anno`), keyed by the producer, its executable and data
+    /// source, the file being annotated, and the environment producers are
+    /// given. Use `-v` to see which producers' output was reused.
+    #[arg(long, global = true)]
+    refresh: bool,
+
+    /// Neither reuse nor store cached producer output
+    #[arg(long, conflicts_with = "refresh", global = true)]
+    no_cache: bool,
+
+    /// Output format [default: terminal]
+    #[arg(long, value_enum)]
+    format: Option<Format>,
+
+    /// Maximum width of each producer's column in terminal columns
+    /// [default: the producer's preference, or 30]
+    ///
+    /// Use `WIDTH` to cap every column or `PRODUCER=WIDTH` to cap the columns
+    /// of a specific producer. May be given multiple times. Annotations cut
+    /// short end with `…`.
+    #[arg(long = "max-width", value_name = "[PRODUCER=]WIDTH")]
+    max_widths: Vec<MaxWidth>,
+
+    /// Wrap annotations too wide for their column onto further lines in
+    /// terminal output, instead of cutting them short
+    #[arg(long)]
+    wrap: bool,
+
+    /// Explain the symbols producers use (such as `x`) above the header in
+    /// terminal output
+    ///
+    /// Only producers which describe their symbols have a legend. HTML output
+    /// always shows legends in column tooltips.
+    #[arg(long)]
+    legend: bool,
+
+    /// When to use colours in terminal output [default: always]
+    #[arg(long, value_enum, value_name = "WHEN")]
+    color: Option<ColorChoice>,
+
     #[command(flatten)]
-    verbose: clap_verbosity_flag::Verbosity,
+    verbose: clap_verbosity_flag::Verbosity<clap_verbosity_flag::WarnLevel>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Run a Language Server Protocol server to show annotations in editors
+    ///
+    /// Each file opened in the editor is annotated by the producers given
+    /// with `-p` (or `--preset`), and again each time it is saved.
+    /// Annotations are shown as inlay hints and hover text.
+    Lsp {
+        /// Also publish annotations with a severity as diagnostics
+        #[arg(long)]
+        diagnostics: bool,
+
+        /// Also show a code lens at the top of each file summarising how many
+        /// lines each producer annotates
+        #[arg(long)]
+        code_lens: bool,
+    },
+
+    /// Work with annotation data producers
+    Producers {
+        #[command(subcommand)]
+        command: ProducersCommand,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ProducersCommand {
+    /// List producers found in `PATH` and any `producer-dirs` from
+    /// `anno.toml`, along with the description each gives of itself
+    ///
+    /// Producers describe themselves when run with `--anno-describe`. Those
+    /// that do not are still listed, without any details.
+    List {
+        /// Write the list as JSON
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+impl Cli {
+    /// Whether `--diff` or `--no-diff` was given last, if either
+    fn diff(&self) -> Option<bool> {
+        flag(self.diff, self.no_diff)
+    }
+
+    /// Whether `--diff-only` or `--no-diff-only` was given last, if either
+    fn diff_only(&self) -> Option<bool> {
+        flag(self.diff_only, self.no_diff_only)
+    }
+}
+
+/// Value of a flag which can also be turned off, if given at all
+fn flag(on: bool, off: bool) -> Option<bool> {
+    match (on, off) {
+        (true, _) => Some(true),
+        (_, true) => Some(false),
+        _ => None,
+    }
 }
 
 fn main() -> Result<()> {
-    // TODO: Customise parsing to allow producer without trailing `:`
     let cli = Cli::parse();
 
     env_logger::Builder::new()
@@ -57,151 +308,467 @@ fn main() -> Result<()> {
 
     debug!("CLI: {:?}", cli);
 
-    if cli.producers.is_empty() {
-        eprintln!("Warning: No producers, displaying file without annotations");
+    match cli.command {
+        Some(Command::Lsp {
+            diagnostics,
+            code_lens,
+        }) => {
+            let options = LspOptions {
+                diagnostics,
+                code_lens,
+            };
+            return lsp::serve(|file| Ok(session(&cli, file)?.0), options);
+        }
+        Some(Command::Producers {
+            command: ProducersCommand::List { json },
+        }) => return list_producers(&cli, json, &mut io::stdout().lock()),
+        None => {}
     }
 
-    // Read the file first to check line count
-    let target_path = cli.file.absolutize()?;
-    let target_content = fs::read_to_string(&target_path).with_context(|| {
-        format!(
-            "Unable to read file to be annotated ({})",
-            cli.file.display()
-        )
-    })?;
-    let target_line_count = target_content.lines().count();
-    debug!("Lines: {}", target_line_count);
-
-    // Collect output from each producer
-    // TODO: Run in parallel
-    let mut produced_annotations = Vec::new();
-    for producer in &cli.producers {
-        debug!("Producer: {:?}", producer);
-        let command_name = format!("anno-{}", producer.name());
-        let mut command = cmd!(&command_name);
-        // TODO: Should we pass both used-entered and absolute versions...?
-        command = command.env("ANNO_TARGET", target_path.to_str().unwrap());
-        command = command.env("ANNO_TARGET_LINES", target_line_count.to_string());
-        command = command.env("ANNO_PRODUCER", producer.name());
-        // TODO: Should this be absolute like `ANNO_TARGET`...?
-        command = command.env("ANNO_SOURCE", producer.source());
-        debug!("Command: {:?}", command);
-        let data = command
-            .read()
-            .with_context(|| format!("Annotation producer `{}` failed", &command_name))?;
-        // Cap width at 30 characters to avoid huge columns
-        let max_width = data
-            .lines()
-            .fold(0, |acc, line| max(acc, line.len()))
-            .min(30);
-        // For initial basic annotation format, ensure we have an annotation for every target line
-        assert!(data.lines().count() == target_line_count);
-        let annotations = Annotations { data, max_width };
-        debug!("Annotations: {:?}", annotations);
-        produced_annotations.push(annotations);
-    }
-
-    // Write header
-    for producer_with_annotations in cli.producers.iter().zip(produced_annotations.iter()) {
-        print!(
-            "{:width$.width$} | ",
-            producer_with_annotations.0.name(),
-            width = producer_with_annotations.1.max_width
-        );
-    }
-    println!("");
-
-    // Write file content with annotations added
-    let mut produced_table: Vec<(Peekable<Lines>, usize)> = produced_annotations
-        .iter()
-        .map(|a| (a.data.lines().peekable(), a.max_width))
-        .collect();
-    let diffing = cli.diff && produced_table.len() >= 2;
-    for line in target_content.lines() {
-        let mut before_annotation = None;
-        let mut after_annotation = None;
-
-        // Track before and after annotations in diff mode
-        if diffing || cli.diff_only {
-            before_annotation = produced_table.get_mut(0).map(|p| *p.0.peek().unwrap());
-            after_annotation = produced_table.get_mut(1).map(|p| *p.0.peek().unwrap());
-        }
-
-        // Skip line if all annotations match in diff only mode
-        if cli.diff_only && before_annotation == after_annotation {
-            // Advance each column's iterator
-            for column in produced_table.iter_mut() {
-                column.0.next();
+    let reads_stdin = cli.files.iter().any(|file| file == Path::new("-"));
+    let multiple = cli.files.len() > 1
+        || cli
+            .files
+            .iter()
+            .any(|file| file.is_dir() || (is_pattern(file) && !file.exists()));
+    let targets = if multiple {
+        if reads_stdin {
+            return Err(anyhow!("`-` cannot be combined with other files"));
+        }
+        let single_file_options = [
+            ("--tui", cli.tui),
+            ("--watch", cli.watch),
+            ("--target-name", cli.target_name.is_some()),
+        ];
+        for (option, used) in single_file_options {
+            if used {
+                return Err(anyhow!("`{}` can only be used with a single file", option));
             }
-            continue;
         }
+        expand_targets(&cli.files)?
+    } else {
+        cli.files.clone()
+    };
+    let file = targets
+        .first()
+        .ok_or_else(|| anyhow!("No files to annotate"))?;
+    let target = match &cli.target_name {
+        Some(target_name) => target_name.as_path(),
+        None if reads_stdin => Path::new("stdin"),
+        None => file,
+    };
+    let (session, display) = session(&cli, target)?;
+    let only = cli.diff_only().or(display.diff_only).unwrap_or_default();
+    let baseline = cli.diff_against.or(display.diff_against);
+    let selects_columns = cli.diff_against.is_some() || cli.diff_columns.is_some();
+    let diff = DiffOptions {
+        highlight: cli
+            .diff()
+            .unwrap_or_else(|| display.diff.unwrap_or_default() || (selects_columns && !only)),
+        only,
+        baseline: baseline.unwrap_or_else(|| session.default_baseline()),
+        columns: cli.diff_columns.or(display.diff_columns),
+        context: cli.context.or(display.context).unwrap_or_default(),
+    };
+    diff.validate(session.producers.len(), baseline.is_some())?;
 
-        for i in 0..produced_table.len() {
-            let annotation = produced_table.get_mut(i).unwrap().0.next().unwrap();
-            let mut painted_annotation = Paint::new(annotation);
+    if cli.tui {
+        if reads_stdin {
+            return Err(anyhow!(
+                "`--tui` cannot read the file to annotate from stdin"
+            ));
+        }
+        return anno::tui::run(&session, file, &diff);
+    }
 
-            // Highlight any differences in diff mode
-            if diffing {
-                if i == 0 {
-                    if let Some(after_annotation) = after_annotation {
-                        if annotation != after_annotation {
-                            painted_annotation = painted_annotation.fg(Color::Red).bold();
-                        }
-                    }
-                }
-                if i == 1 {
-                    if let Some(before_annotation) = before_annotation {
-                        if annotation != before_annotation {
-                            painted_annotation = painted_annotation.fg(Color::Green).bold();
-                        }
-                    }
-                }
+    cli.color.or(display.color).unwrap_or_default().apply();
+    let format = cli.format.or(display.format).unwrap_or_default();
+    let renderer = format.renderer_with(TerminalRenderer {
+        legend: cli.legend || display.legend.unwrap_or_default(),
+        wrap: cli.wrap || display.wrap.unwrap_or_default(),
+    });
+    let show = |annotated: &Annotated| {
+        let table = annotated.table(diff.clone());
+        let mut out = io::stdout().lock();
+        if cli.summary {
+            write_summary(&Summary::new(&table), format, &mut out)
+        } else {
+            renderer.render(&table, &mut out)
+        }
+    };
+
+    if multiple {
+        let annotated = session.annotate_all(&targets)?;
+        let tables: Vec<Table> = annotated
+            .iter()
+            .map(|annotated| annotated.table(diff.clone()))
+            .collect();
+        let mut out = io::stdout().lock();
+        if cli.summary {
+            return write_summaries(&tables, format, &mut out);
+        }
+        return renderer.render_all(&tables, &mut out);
+    }
+
+    if cli.watch {
+        if reads_stdin {
+            return Err(anyhow!(
+                "`--watch` cannot read the file to annotate from stdin"
+            ));
+        }
+        // Replace earlier output when redrawing in a terminal
+        let clear = format == Format::Terminal && io::stdout().is_terminal();
+        return watch::watch(&session, file, |annotated| {
+            if clear {
+                print!("\x1b[2J\x1b[H");
             }
+            show(annotated)
+        });
+    }
+
+    let annotated = if reads_stdin || cli.target_name.is_some() {
+        session.annotate_content(target, read_content(file)?)?
+    } else {
+        session.annotate(file)?
+    };
+    show(&annotated)
+}
+
+fn write_summary(summary: &Summary, format: Format, out: &mut dyn Write) -> Result<()> {
+    match format {
+        Format::Terminal => summary.write_text(out)?,
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut *out, summary)?;
+            writeln!(out)?;
+        }
+        Format::Jsonl => {
+            serde_json::to_writer(&mut *out, summary)?;
+            writeln!(out)?;
+        }
+        Format::Html => return Err(anyhow!("Summaries cannot be written as HTML")),
+    }
+    Ok(())
+}
 
-            // Write current producer's annotation value
-            print!(
-                "{:width$.width$} | ",
-                painted_annotation,
-                width = produced_table.get(i).unwrap().1
-            );
+/// Writes a summary for each of several files
+fn write_summaries(tables: &[Table], format: Format, out: &mut dyn Write) -> Result<()> {
+    match format {
+        Format::Terminal => {
+            for (i, table) in tables.iter().enumerate() {
+                if i > 0 {
+                    writeln!(out)?;
+                }
+                writeln!(out, "==> {} <==", table.target)?;
+                Summary::new(table).write_text(out)?;
+            }
         }
+        Format::Json => {
+            let summaries: Vec<_> = tables
+                .iter()
+                .map(|table| json!({ "target": table.target, "summary": Summary::new(table) }))
+                .collect();
+            serde_json::to_writer_pretty(&mut *out, &summaries)?;
+            writeln!(out)?;
+        }
+        Format::Jsonl => {
+            for table in tables {
+                let summary = json!({ "target": table.target, "summary": Summary::new(table) });
+                serde_json::to_writer(&mut *out, &summary)?;
+                writeln!(out)?;
+            }
+        }
+        Format::Html => return Err(anyhow!("Summaries cannot be written as HTML")),
+    }
+    Ok(())
+}
+
+/// Lists the producers available from the current directory, with their
+/// descriptions
+fn list_producers(cli: &Cli, json: bool, out: &mut dyn Write) -> Result<()> {
+    let config = Config::discover_in(&env::current_dir()?)?;
+    let found = discover(&config.producer_dirs);
+    let paths: Vec<PathBuf> = found.iter().map(|producer| producer.path.clone()).collect();
+    let descriptions = describe(&paths, cache(cli).as_ref());
 
-        // Write line from file being annotated
-        println!("{}", line);
+    if json {
+        let producers: Vec<_> = found
+            .iter()
+            .zip(&descriptions)
+            .map(|(producer, description)| {
+                json!({
+                    "name": producer.name,
+                    "path": producer.path,
+                    "description": description,
+                })
+            })
+            .collect();
+        serde_json::to_writer_pretty(&mut *out, &producers)?;
+        writeln!(out)?;
+        return Ok(());
     }
 
+    if found.is_empty() {
+        writeln!(out, "No producers found")?;
+    }
+    for (i, (producer, description)) in found.iter().zip(&descriptions).enumerate() {
+        if i > 0 {
+            writeln!(out)?;
+        }
+        writeln!(out, "{}", producer.name)?;
+        match description {
+            Some(description) => write_description(description, out)?,
+            None => writeln!(out, "  (does not describe itself)")?,
+        }
+        writeln!(out, "  Path: {}", producer.path.display())?;
+    }
     Ok(())
 }
 
-#[derive(Clone, Debug)]
-struct Producer(Url);
+fn write_description(description: &Description, out: &mut dyn Write) -> Result<()> {
+    if let Some(text) = &description.description {
+        writeln!(out, "  {}", text)?;
+    }
+    let source = match description.source {
+        SourceUse::Required => "required",
+        SourceUse::Optional => "optional",
+        SourceUse::None => "not used",
+    };
+    match &description.source_description {
+        Some(text) if description.source != SourceUse::None => {
+            writeln!(out, "  Data source: {} ({})", source, text)?
+        }
+        _ => writeln!(out, "  Data source: {}", source)?,
+    }
+    match &description.params {
+        Some(params) if params.is_empty() => writeln!(out, "  Parameters: none")?,
+        Some(params) => {
+            writeln!(out, "  Parameters:")?;
+            for param in params {
+                let required = if param.required { " (required)" } else { "" };
+                match &param.description {
+                    Some(text) => writeln!(out, "    {}{}: {}", param.name, required, text)?,
+                    None => writeln!(out, "    {}{}", param.name, required)?,
+                }
+            }
+        }
+        None => {}
+    }
+    if let Some(env) = description.env.as_ref().filter(|env| !env.is_empty()) {
+        writeln!(out, "  Environment: {}", env.join(", "))?;
+    }
+    if !description.protocols.is_empty() {
+        writeln!(out, "  Protocols: {}", description.protocols.join(", "))?;
+    }
+    let capabilities = &description.capabilities;
+    if let Some(kind) = capabilities.kind {
+        let kind = match kind {
+            ValueKind::Boolean => "boolean",
+            ValueKind::Set => "set",
+            ValueKind::Number => "number",
+            ValueKind::Text => "text",
+        };
+        writeln!(out, "  Values: {}", kind)?;
+    }
+    if !capabilities.diffable {
+        writeln!(out, "  Left out of diffs unless selected")?;
+    }
+    for (symbol, meaning) in &capabilities.legend {
+        writeln!(out, "  Legend: {} = {}", symbol, meaning)?;
+    }
+    Ok(())
+}
 
-impl Producer {
-    fn parse(input: &str) -> Result<Producer> {
-        Ok(Producer(Url::parse(input)?))
+/// Cache for producer output and descriptions, unless disabled
+fn cache(cli: &Cli) -> Option<Cache> {
+    if cli.no_cache {
+        None
+    } else {
+        Cache::new(cli.refresh)
     }
+}
 
-    fn name(&self) -> &str {
-        self.0.scheme()
+/// Reads the content of the file to annotate, which is `-` for stdin
+fn read_content(file: &Path) -> Result<String> {
+    if file == Path::new("-") {
+        let mut content = String::new();
+        io::stdin()
+            .read_to_string(&mut content)
+            .context("Unable to read file to be annotated from stdin")?;
+        return Ok(content);
+    }
+    fs::read_to_string(file)
+        .with_context(|| format!("Unable to read file to be annotated ({})", file.display()))
+}
+
+/// Builds a session for annotating `file` from the command line and any
+/// configuration applying to it, along with the display options to use
+fn session(cli: &Cli, file: &Path) -> Result<(Session, Display)> {
+    let mut timeouts = Timeouts {
+        global: cli.timeout,
+        ..Default::default()
+    };
+    for timeout in &cli.producer_timeouts {
+        match &timeout.name {
+            Some(name) => {
+                timeouts.named.insert(name.clone(), timeout.duration);
+            }
+            None => timeouts.producer = Some(timeout.duration),
+        }
+    }
+    // Settings from the command line take precedence over the preset, which
+    // takes precedence over the rest of the config
+    let config = Config::discover(file)?;
+    debug!("Config: {:?}", config);
+    let mut preset_producers = Vec::new();
+    let mut env = config.env.clone();
+    let mut display = config.display.clone();
+    if let Some(name) = &cli.preset {
+        let preset = config.preset(name)?;
+        preset_producers = preset.producers.clone();
+        env.extend(preset.env.clone());
+        display = preset.display.clone().or(display);
     }
 
-    fn source(&self) -> &str {
-        self.0.path()
+    let producers = preset_producers
+        .into_iter()
+        .chain(cli.producers.iter().cloned())
+        .map(|producer| {
+            let producer = match config.sources.get(producer.name()) {
+                Some(source) if producer.source().is_empty() => producer.with_source(source),
+                _ => producer,
+            };
+            // Only producers that would be searched for in `PATH` are looked
+            // for in producer directories
+            let searched = producer.command().components().count() == 1;
+            let producer = match config.producer_command(producer.name()) {
+                Some(command) if searched => producer.with_command(command),
+                _ => producer,
+            };
+            match cli
+                .producer_paths
+                .iter()
+                .find(|p| p.name == producer.name())
+            {
+                Some(producer_path) => producer.with_command(&producer_path.path),
+                None => producer,
+            }
+        })
+        .collect::<Vec<_>>();
+    if producers.is_empty() {
+        eprintln!("Warning: No producers, displaying file without annotations");
     }
+    let mut session = Session {
+        producers,
+        timeouts,
+        on_mismatch: cli.on_mismatch,
+        env,
+        max_width: cli
+            .max_widths
+            .iter()
+            .rev()
+            .find(|max_width| max_width.name.is_none())
+            .map(|max_width| max_width.width)
+            .or(display.max_width),
+        column_widths: display
+            .column_widths
+            .clone()
+            .unwrap_or_default()
+            .into_iter()
+            .chain(
+                cli.max_widths
+                    .iter()
+                    .filter_map(|max_width| Some((max_width.name.clone()?, max_width.width))),
+            )
+            .collect(),
+        selections: cli
+            .lines
+            .iter()
+            .cloned()
+            .chain(cli.function.iter().cloned().map(Selection::Function))
+            .collect(),
+        cache: cache(cli),
+        capabilities: Vec::new(),
+        reads_env: Vec::new(),
+    };
+    // Report mistakes in producer specs before running anything
+    session.describe_producers()?;
+    Ok((session, display))
 }
 
-impl FromStr for Producer {
+/// Timeout option value, either `SECONDS` for all producers or
+/// `PRODUCER=SECONDS` for a single producer
+#[derive(Clone, Debug)]
+struct ProducerTimeout {
+    name: Option<String>,
+    duration: Duration,
+}
+
+impl FromStr for ProducerTimeout {
     type Err = Error;
 
     fn from_str(input: &str) -> std::result::Result<Self, Self::Err> {
-        Producer::parse(input)
+        let (name, seconds) = match input.split_once('=') {
+            Some((name, seconds)) if !name.is_empty() => (Some(name.to_string()), seconds),
+            Some(_) => return Err(anyhow!("`{}` has no producer name", input)),
+            None => (None, input),
+        };
+        Ok(ProducerTimeout {
+            name,
+            duration: parse_seconds(seconds)?,
+        })
+    }
+}
+
+/// Column width option value, either `WIDTH` for all producers or
+/// `PRODUCER=WIDTH` for a single producer
+#[derive(Clone, Debug)]
+struct MaxWidth {
+    name: Option<String>,
+    width: usize,
+}
+
+impl FromStr for MaxWidth {
+    type Err = Error;
+
+    fn from_str(input: &str) -> std::result::Result<Self, Self::Err> {
+        let (name, width) = match input.split_once('=') {
+            Some((name, width)) if !name.is_empty() => (Some(name.to_string()), width),
+            Some(_) => return Err(anyhow!("`{}` has no producer name", input)),
+            None => (None, input),
+        };
+        let width = width
+            .parse()
+            .map_err(|_| anyhow!("`{}` is not a width in terminal columns", width))?;
+        Ok(MaxWidth { name, width })
+    }
+}
+
+/// Producer executable option value, `PRODUCER=PATH`
+#[derive(Clone, Debug)]
+struct ProducerPath {
+    name: String,
+    path: PathBuf,
+}
+
+impl FromStr for ProducerPath {
+    type Err = Error;
+
+    fn from_str(input: &str) -> std::result::Result<Self, Self::Err> {
+        match input.split_once('=') {
+            Some((name, path)) if !name.is_empty() && !path.is_empty() => Ok(ProducerPath {
+                name: name.to_string(),
+                path: PathBuf::from(path),
+            }),
+            _ => Err(anyhow!("`{}` is not of the form `PRODUCER=PATH`", input)),
+        }
     }
 }
 
-#[derive(Debug)]
-struct Annotations {
-    /// Raw data from the annotation producer
-    data: String,
-    /// Maximum width of `data` across all lines
-    max_width: usize,
+/// Parses a (possibly fractional) number of seconds
+fn parse_seconds(input: &str) -> Result<Duration> {
+    let seconds: f64 = input
+        .parse()
+        .map_err(|_| anyhow!("`{}` is not a number of seconds", input))?;
+    Duration::try_from_secs_f64(seconds)
+        .map_err(|_| anyhow!("`{}` is not a valid number of seconds", input))
 }
@@ -0,0 +1,160 @@
+use std::{collections::BTreeMap, env, path::PathBuf, str::FromStr};
+
+use anyhow::{anyhow, Error, Ok, Result};
+use url::form_urlencoded;
+
+/// Annotation data producer, identified by a URI-like spec such as
+/// `producer:/path/to/data/source?param=value`
+#[derive(Clone, Debug)]
+pub struct Producer {
+    name: String,
+    source: String,
+    query: Option<String>,
+    /// Explicit path to the producer executable, if not found via `PATH`
+    command: Option<PathBuf>,
+    /// Name or executable path as written in the spec
+    spec_name: String,
+    uri: String,
+}
+
+impl Producer {
+    /// Parses a producer spec
+    ///
+    /// The following forms are accepted:
+    ///
+    /// - `name` or `name:`
+    /// - `name:source`
+    /// - `name:source?param=value`
+    /// - `path/to/anno-name:source?param=value`, which runs the given
+    ///   executable instead of searching `PATH` (the name is taken from the
+    ///   file name, without any `anno-` prefix)
+    pub fn parse(input: &str) -> Result<Producer> {
+        let invalid = |reason: String| anyhow!("Invalid producer `{}`: {}", input, reason);
+
+        let (spec, query) = match input.split_once('?') {
+            Some((spec, query)) => (spec, Some(query.to_string())),
+            None => (input, None),
+        };
+        let (spec_name, source) = spec.split_once(':').unwrap_or((spec, ""));
+        if spec_name.is_empty() {
+            return Err(invalid("missing producer name before `:`".to_string()));
+        }
+
+        // Names containing a path separator refer to a specific executable
+        let (name, command) = if spec_name.contains('/') {
+            let command = PathBuf::from(spec_name);
+            let file_name = command
+                .file_name()
+                .map(|file_name| file_name.to_string_lossy().into_owned())
+                .ok_or_else(|| invalid(format!("`{}` is not a path to a file", spec_name)))?;
+            let name = file_name
+                .strip_prefix("anno-")
+                .unwrap_or(&file_name)
+                .to_string();
+            (name, Some(command))
+        } else {
+            (spec_name.to_string(), None)
+        };
+        if let Some(c) = name
+            .chars()
+            .find(|c| !(c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.')))
+        {
+            return Err(invalid(format!(
+                "producer name `{}` contains `{}` (only letters, digits, `-`, `_`, and `.` are allowed)",
+                name, c
+            )));
+        }
+
+        let mut producer = Producer {
+            name,
+            source: source.to_string(),
+            query,
+            command,
+            spec_name: spec_name.to_string(),
+            uri: String::new(),
+        };
+        producer.update_uri();
+        Ok(producer)
+    }
+
+    fn update_uri(&mut self) {
+        self.uri = format!("{}:{}", self.spec_name, self.source);
+        if let Some(query) = &self.query {
+            self.uri.push('?');
+            self.uri.push_str(query);
+        }
+    }
+
+    /// Replaces the data source passed to the producer
+    pub fn with_source(mut self, source: impl Into<String>) -> Producer {
+        self.source = source.into();
+        self.update_uri();
+        self
+    }
+
+    /// Runs this producer using the given executable instead of searching
+    /// `PATH` for `anno-<name>`
+    pub fn with_command(mut self, command: impl Into<PathBuf>) -> Producer {
+        self.command = Some(command.into());
+        self
+    }
+
+    /// Name of the producer, used to find the `anno-<name>` command
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Normalised spec identifying the producer, always including the `:`
+    /// after the name
+    pub fn uri(&self) -> &str {
+        &self.uri
+    }
+
+    /// Data source passed to the producer, which may be empty
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Query parameters passed to the producer, with all values for each key
+    /// in the order given
+    pub fn params(&self) -> BTreeMap<String, Vec<String>> {
+        let mut params: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        let query = self.query.as_deref().unwrap_or_default();
+        for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+            params
+                .entry(key.into_owned())
+                .or_default()
+                .push(value.into_owned());
+        }
+        params
+    }
+
+    /// Command to run for this producer, either an explicit executable or
+    /// `anno-<name>` to be found via `PATH`
+    pub fn command(&self) -> PathBuf {
+        match &self.command {
+            Some(path) => path.clone(),
+            None => PathBuf::from(format!("anno-{}", self.name)),
+        }
+    }
+
+    /// Path to the executable run for this producer, searching `PATH` when
+    /// needed, or `None` if there is no such file
+    pub fn executable(&self) -> Option<PathBuf> {
+        let command = self.command();
+        if command.components().count() > 1 {
+            return command.is_file().then_some(command);
+        }
+        env::split_paths(&env::var_os("PATH")?)
+            .map(|dir| dir.join(&command))
+            .find(|path| path.is_file())
+    }
+}
+
+impl FromStr for Producer {
+    type Err = Error;
+
+    fn from_str(input: &str) -> std::result::Result<Self, Self::Err> {
+        Producer::parse(input)
+    }
+}
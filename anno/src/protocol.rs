@@ -0,0 +1,224 @@
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+
+use crate::{
+    annotations::{Annotation, Position, Range, Severity},
+    validate::{validate_line_count, validate_record_line, MismatchPolicy},
+};
+
+/// Output protocols understood by Anno, in order of preference, passed to
+/// producers via `ANNO_PROTOCOLS`
+pub const SUPPORTED_PROTOCOLS: &str = "jsonl/1,lines";
+
+/// First record of JSON Lines output, identifying the protocol in use
+#[derive(Debug, Deserialize)]
+struct Header {
+    protocol: String,
+    version: u32,
+    /// Whether the output annotates every target in `ANNO_TARGETS`, with each
+    /// record naming the target it applies to
+    #[serde(default)]
+    targets: bool,
+}
+
+/// Annotation for a single line or a range in JSON Lines output
+#[derive(Debug, Deserialize)]
+struct Record {
+    /// Path of the target (from `ANNO_TARGETS`) the annotation applies to,
+    /// when the output annotates several targets
+    target: Option<String>,
+    /// Line number (1-based) the annotation applies to
+    line: Option<usize>,
+    /// Region the annotation applies to, as an alternative to `line`
+    range: Option<Range>,
+    #[serde(default)]
+    value: RecordValue,
+    detail: Option<String>,
+    severity: Option<Severity>,
+}
+
+/// Value of a record, either text or a set of tokens
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum RecordValue {
+    Text(String),
+    Set(Vec<String>),
+}
+
+impl Default for RecordValue {
+    fn default() -> Self {
+        RecordValue::Text(String::new())
+    }
+}
+
+impl Record {
+    /// Region covered by the record, whether given as a line or a range
+    fn range(&self) -> Option<Range> {
+        match (self.line, self.range) {
+            (Some(line), None) => {
+                let position = Position { line, column: None };
+                Some(Range {
+                    start: position,
+                    end: position,
+                })
+            }
+            (None, Some(range)) => Some(range),
+            _ => None,
+        }
+    }
+
+    /// Annotation for one line covered by the record
+    fn annotation(&self, range: &Range, line: usize) -> Annotation {
+        let annotation = match &self.value {
+            RecordValue::Text(value) => Annotation {
+                value: value.clone(),
+                ..Default::default()
+            },
+            RecordValue::Set(elements) => Annotation::set(elements.clone()),
+        };
+        Annotation {
+            detail: self.detail.clone(),
+            severity: self.severity,
+            spans: range.span(line).into_iter().collect(),
+            ..annotation
+        }
+    }
+}
+
+/// Parses raw producer output into an annotation for each target line
+///
+/// Output starting with a JSON Lines header is parsed as sparse records, with
+/// any lines lacking a record left blank. All other output is treated as one
+/// line of text per target line.
+pub fn parse_output(
+    producer: &str,
+    data: String,
+    line_count: usize,
+    policy: MismatchPolicy,
+) -> Result<Vec<Annotation>> {
+    match data.lines().next().and_then(parse_header) {
+        Some(header) => {
+            check_header(producer, &header)?;
+            let mut annotations = parse_json_lines(producer, &data, None, &[line_count], policy)?;
+            Ok(annotations.remove(0))
+        }
+        None => {
+            let data = validate_line_count(producer, data, line_count, policy)?;
+            Ok(data
+                .lines()
+                .map(|line| Annotation {
+                    value: line.to_string(),
+                    ..Default::default()
+                })
+                .collect())
+        }
+    }
+}
+
+/// Whether raw producer output annotates every target in `ANNO_TARGETS`,
+/// rather than only the first
+pub fn covers_targets(data: &str) -> bool {
+    data.lines()
+        .next()
+        .and_then(parse_header)
+        .is_some_and(|header| header.targets)
+}
+
+/// Parses raw producer output covering every target in `ANNO_TARGETS` into
+/// an annotation for each line of each target, given as paths and line
+/// counts in the same order as `ANNO_TARGETS`
+pub fn parse_targets_output(
+    producer: &str,
+    data: &str,
+    targets: &[(&str, usize)],
+    policy: MismatchPolicy,
+) -> Result<Vec<Vec<Annotation>>> {
+    let header = data
+        .lines()
+        .next()
+        .and_then(parse_header)
+        .filter(|header| header.targets)
+        .ok_or_else(|| {
+            anyhow!(
+                "Annotation producer `{}` did not annotate every target",
+                producer
+            )
+        })?;
+    check_header(producer, &header)?;
+    let (paths, line_counts): (Vec<&str>, Vec<usize>) = targets.iter().copied().unzip();
+    parse_json_lines(producer, data, Some(&paths), &line_counts, policy)
+}
+
+fn check_header(producer: &str, header: &Header) -> Result<()> {
+    if header.protocol == "jsonl" && header.version == 1 {
+        return Ok(());
+    }
+    Err(anyhow!(
+        "Annotation producer `{}` uses unsupported protocol `{}/{}` (supported: {})",
+        producer,
+        header.protocol,
+        header.version,
+        SUPPORTED_PROTOCOLS,
+    ))
+}
+
+fn parse_header(line: &str) -> Option<Header> {
+    if !line.trim_start().starts_with('{') {
+        return None;
+    }
+    serde_json::from_str(line).ok()
+}
+
+/// Parses JSON Lines records into annotations for each target, where records
+/// name their target from `paths` when several targets are annotated at once
+fn parse_json_lines(
+    producer: &str,
+    data: &str,
+    paths: Option<&[&str]>,
+    line_counts: &[usize],
+    policy: MismatchPolicy,
+) -> Result<Vec<Vec<Annotation>>> {
+    let mut annotations: Vec<Vec<Annotation>> = line_counts
+        .iter()
+        .map(|&line_count| vec![Annotation::default(); line_count])
+        .collect();
+    // Skip header, which has already been checked
+    for (i, output_line) in data.lines().enumerate().skip(1) {
+        if output_line.trim().is_empty() {
+            continue;
+        }
+        let invalid_record = || {
+            format!(
+                "Annotation producer `{}` supplied an invalid record on output line {}: {}",
+                producer,
+                i + 1,
+                output_line
+            )
+        };
+        let record: Record = serde_json::from_str(output_line).with_context(invalid_record)?;
+        let range = record
+            .range()
+            .filter(Range::is_valid)
+            .ok_or_else(|| anyhow!("Record must have either a `line` or a valid `range`"))
+            .with_context(invalid_record)?;
+        let target = match paths {
+            Some(paths) => record
+                .target
+                .as_deref()
+                .and_then(|target| paths.iter().position(|path| *path == target))
+                .ok_or_else(|| anyhow!("Record must have a `target` from `ANNO_TARGETS`"))
+                .with_context(invalid_record)?,
+            None => 0,
+        };
+        let line_count = line_counts[target];
+        let target_annotations = &mut annotations[target];
+        validate_record_line(producer, range.start.line, line_count, policy)?;
+        validate_record_line(producer, range.end.line, line_count, policy)?;
+        // Keep the part of the range within the target, when truncating
+        let lines = range.start.line.max(1)..=range.end.line.min(line_count);
+        for line in lines {
+            target_annotations[line - 1].merge(record.annotation(&range, line));
+        }
+    }
+    Ok(annotations)
+}
@@ -0,0 +1,277 @@
+use std::io::Write;
+
+use anyhow::Result;
+
+use crate::{
+    annotations::{Annotation, Severity, Span},
+    describe::Align,
+    render::Renderer,
+    summary::FileSummary,
+    table::{Column, DiffHighlight, Table},
+    width,
+};
+
+const STYLE: &str = r#"
+body { margin: 0; font-family: ui-monospace, SFMono-Regular, Menlo, Consolas, monospace; font-size: 13px; }
+h1 { margin: 0; padding: 8px 12px; font-size: 15px; background: #f6f8fa; border-bottom: 1px solid #d0d7de; }
+h2 { margin: 16px 0 0; padding: 8px 12px; font-size: 14px; background: #f6f8fa; border-top: 1px solid #d0d7de; border-bottom: 1px solid #d0d7de; }
+table.index { margin: 8px 0; }
+table.index th { position: static; }
+td.count, th.count { text-align: right; }
+table { border-collapse: collapse; }
+th { position: sticky; top: 0; background: #f6f8fa; border-bottom: 1px solid #d0d7de; text-align: left; font-weight: 600; }
+th, td { padding: 0 8px; white-space: pre; vertical-align: top; }
+td.annotation, th.annotation { border-right: 1px solid #d0d7de; }
+td.right, th.right { text-align: right; }
+td.number { text-align: right; color: #6e7781; user-select: none; }
+td.number a { color: inherit; text-decoration: none; }
+tr:target { background: #fff8c5; }
+tr:hover { background: #f6f8fa; }
+.truncated { cursor: help; text-decoration: underline dotted; }
+.severity-warning { color: #9a6700; }
+.severity-error { color: #cf222e; }
+.diff-before { color: #cf222e; font-weight: bold; }
+.diff-after { color: #1a7f37; font-weight: bold; }
+.span { text-decoration: underline; }
+tr.skipped td { color: #6e7781; background: #f6f8fa; }
+"#;
+
+/// Standalone HTML page
+#[derive(Clone, Copy, Debug, Default)]
+pub struct HtmlRenderer;
+
+impl Renderer for HtmlRenderer {
+    fn render(&self, table: &Table, out: &mut dyn Write) -> Result<()> {
+        write_page_start(table.target, out)?;
+        writeln!(out, "<h1>{}</h1>", escape(table.target))?;
+        write_table(table, "", out)?;
+        write_page_end(out)
+    }
+
+    fn render_all(&self, tables: &[Table], out: &mut dyn Write) -> Result<()> {
+        let title = format!("{} files", tables.len());
+        write_page_start(&title, out)?;
+        writeln!(out, "<h1>{}</h1>", escape(&title))?;
+
+        // Write index linking to each file, with the lines each producer
+        // annotates
+        writeln!(out, "<table class=\"index\">")?;
+        write!(
+            out,
+            "<thead><tr><th>File</th><th class=\"count\">Lines</th>"
+        )?;
+        if let Some(table) = tables.first() {
+            for column in &table.columns {
+                write!(
+                    out,
+                    "<th class=\"count\" title=\"{}\">{}</th>",
+                    escape(column.producer.uri()),
+                    escape(column.producer.name())
+                )?;
+            }
+        }
+        writeln!(out, "</tr></thead>")?;
+        writeln!(out, "<tbody>")?;
+        for (i, table) in tables.iter().enumerate() {
+            let summary = FileSummary::new(table);
+            write!(
+                out,
+                "<tr><td><a href=\"#F{}\">{}</a></td><td class=\"count\">{}</td>",
+                i + 1,
+                escape(table.target),
+                summary.line_count
+            )?;
+            for producer in &summary.producers {
+                write!(out, "<td class=\"count\">{}</td>", producer.annotated_lines)?;
+            }
+            writeln!(out, "</tr>")?;
+        }
+        writeln!(out, "</tbody>")?;
+        writeln!(out, "</table>")?;
+
+        for (i, table) in tables.iter().enumerate() {
+            writeln!(out, "<h2 id=\"F{}\">{}</h2>", i + 1, escape(table.target))?;
+            write_table(table, &format!("F{}-", i + 1), out)?;
+        }
+        write_page_end(out)
+    }
+}
+
+fn write_page_start(title: &str, out: &mut dyn Write) -> Result<()> {
+    writeln!(out, "<!DOCTYPE html>")?;
+    writeln!(out, "<html>")?;
+    writeln!(out, "<head>")?;
+    writeln!(out, "<meta charset=\"utf-8\">")?;
+    writeln!(out, "<title>{}</title>", escape(title))?;
+    writeln!(out, "<style>{}</style>", STYLE)?;
+    writeln!(out, "</head>")?;
+    writeln!(out, "<body>")?;
+    Ok(())
+}
+
+fn write_page_end(out: &mut dyn Write) -> Result<()> {
+    writeln!(out, "</body>")?;
+    writeln!(out, "</html>")?;
+    Ok(())
+}
+
+/// Writes the annotated file as a table, with row IDs (for linking to lines)
+/// starting with `id_prefix`
+fn write_table(table: &Table, id_prefix: &str, out: &mut dyn Write) -> Result<()> {
+    writeln!(out, "<table>")?;
+
+    // Write header
+    write!(out, "<thead><tr><th></th>")?;
+    for column in &table.columns {
+        // Tooltips also explain any symbols the producer uses
+        let mut title = column.producer.uri().to_string();
+        for (symbol, meaning) in &column.capabilities.legend {
+            title.push_str(&format!("\n{}: {}", symbol, meaning));
+        }
+        write!(
+            out,
+            "<th class=\"{}\" title=\"{}\">{}</th>",
+            column_classes(column),
+            escape(&title),
+            escape(column.producer.name())
+        )?;
+    }
+    writeln!(out, "<th></th></tr></thead>")?;
+
+    // Write file content with annotations added
+    writeln!(out, "<tbody>")?;
+    for (index, row) in table.rows.iter().enumerate() {
+        write_skipped(table, index, out)?;
+        write!(
+            out,
+            "<tr id=\"{0}L{1}\"><td class=\"number\"><a href=\"#{0}L{1}\">{1}</a></td>",
+            id_prefix, row.number
+        )?;
+        for (i, (column, annotation)) in table.columns.iter().zip(&row.annotations).enumerate() {
+            let mut classes = vec![column_classes(column)];
+            match annotation.severity {
+                Some(Severity::Warning) => classes.push("severity-warning"),
+                Some(Severity::Error) => classes.push("severity-error"),
+                Some(Severity::Info) | None => {}
+            }
+            // Highlight any differences in diff mode
+            match table.diff_highlight(row, i) {
+                Some(DiffHighlight::Before) => classes.push("diff-before"),
+                Some(DiffHighlight::After) => classes.push("diff-after"),
+                None => {}
+            }
+            write!(out, "<td class=\"{}\">", classes.join(" "))?;
+            write_annotation(out, annotation, &table.cell_text(row, i), column.max_width)?;
+            write!(out, "</td>")?;
+        }
+
+        // Write line from file being annotated, underlining any ranges
+        let spans: Vec<Span> = row
+            .annotations
+            .iter()
+            .flat_map(|a| a.spans.iter().copied())
+            .collect();
+        writeln!(out, "<td>{}</td></tr>", underline_spans(row.text, &spans))?;
+    }
+    write_skipped(table, table.rows.len(), out)?;
+    writeln!(out, "</tbody>")?;
+
+    writeln!(out, "</table>")?;
+    Ok(())
+}
+
+/// Writes a separator row for any lines left out before the row at `index`
+fn write_skipped(table: &Table, index: usize, out: &mut dyn Write) -> Result<()> {
+    if let Some(skipped) = table.skipped_before(index) {
+        writeln!(
+            out,
+            "<tr class=\"skipped\"><td class=\"number\">...</td><td colspan=\"{}\">{}</td></tr>",
+            table.columns.len() + 1,
+            if skipped.start() == skipped.end() {
+                format!("line {} skipped", skipped.start())
+            } else {
+                format!("lines {}-{} skipped", skipped.start(), skipped.end())
+            }
+        )?;
+    }
+    Ok(())
+}
+
+/// Classes for cells in `column`, aligning them as the producer prefers
+fn column_classes(column: &Column) -> &'static str {
+    match column.capabilities.alignment() {
+        Align::Left => "annotation",
+        Align::Right => "annotation right",
+    }
+}
+
+/// Writes an annotation's text cut to the column width (ending with an
+/// ellipsis if cut short), with a tooltip containing the full value (and any
+/// detail) when some is hidden
+///
+/// The text may differ from the annotation's value (such as when showing
+/// changes in diff mode), in which case the full value is always available in
+/// the tooltip.
+fn write_annotation(
+    out: &mut dyn Write,
+    annotation: &Annotation,
+    text: &str,
+    width: usize,
+) -> Result<()> {
+    let truncated = width::display_width(text) > width || text != annotation.value;
+    let visible = width::truncate(text, width);
+    let tooltip = match (&annotation.detail, truncated) {
+        (Some(detail), true) => Some(format!("{}\n\n{}", annotation.value, detail)),
+        (Some(detail), false) => Some(detail.clone()),
+        (None, true) => Some(annotation.value.clone()),
+        (None, false) => None,
+    };
+    match tooltip {
+        Some(tooltip) => write!(
+            out,
+            "<span class=\"{}\" title=\"{}\">{}</span>",
+            if truncated { "truncated" } else { "detail" },
+            escape(&tooltip),
+            escape(&visible)
+        )?,
+        None => write!(out, "{}", escape(&visible))?,
+    }
+    Ok(())
+}
+
+/// Marks up the columns of `line` covered by any of `spans`
+fn underline_spans(line: &str, spans: &[Span]) -> String {
+    let mut underlined = String::new();
+    let mut segment_covered = false;
+    for (i, c) in line.char_indices() {
+        let covered = spans.iter().any(|span| span.covers(i, c));
+        if covered && !segment_covered {
+            underlined.push_str("<span class=\"span\">");
+        }
+        if !covered && segment_covered {
+            underlined.push_str("</span>");
+        }
+        segment_covered = covered;
+        underlined.push_str(&escape(&c.to_string()));
+    }
+    if segment_covered {
+        underlined.push_str("</span>");
+    }
+    underlined
+}
+
+/// Escapes text for use in HTML content and attribute values
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,127 @@
+use std::io::Write;
+
+use anyhow::Result;
+use serde::{ser::SerializeMap, Serialize, Serializer};
+
+use crate::{
+    render::Renderer,
+    summary::FileSummary,
+    table::{Row, Table, TableHeader},
+};
+
+/// Entire table as a single JSON document
+#[derive(Serialize)]
+struct Document<'a> {
+    #[serde(flatten)]
+    header: TableHeader<'a>,
+    lines: Vec<Line<'a>>,
+}
+
+/// Tables for several files as a single JSON document, starting with an
+/// index summarising each file
+#[derive(Serialize)]
+struct Report<'a> {
+    index: Vec<FileSummary>,
+    files: Vec<Document<'a>>,
+}
+
+/// Annotations for one line, keyed by producer
+struct Line<'a> {
+    table: &'a Table<'a>,
+    row: &'a Row<'a>,
+}
+
+impl Serialize for Line<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut line = serializer.serialize_map(None)?;
+        line.serialize_entry("line", &self.row.number)?;
+        line.serialize_entry("text", self.row.text)?;
+        line.serialize_entry("annotations", &Annotations(self))?;
+        if self.table.highlighting_diff() || self.table.diff.only {
+            line.serialize_entry("differs", &self.row.differs)?;
+            if self.row.changes.iter().any(Option::is_some) {
+                line.serialize_entry("changes", &Changes(self))?;
+            }
+        }
+        line.end()
+    }
+}
+
+/// Annotations for one line as a map from column key to annotation
+struct Annotations<'a>(&'a Line<'a>);
+
+impl Serialize for Annotations<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let Line { table, row } = self.0;
+        let mut annotations = serializer.serialize_map(Some(table.columns.len()))?;
+        for (column, annotation) in table.columns.iter().zip(&row.annotations) {
+            annotations.serialize_entry(&column.key, annotation)?;
+        }
+        annotations.end()
+    }
+}
+
+/// Differences from the baseline for set annotations, keyed by column
+struct Changes<'a>(&'a Line<'a>);
+
+impl Serialize for Changes<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let Line { table, row } = self.0;
+        let mut changes = serializer.serialize_map(None)?;
+        for (column, change) in table.columns.iter().zip(&row.changes) {
+            if let Some(change) = change {
+                changes.serialize_entry(&column.key, change)?;
+            }
+        }
+        changes.end()
+    }
+}
+
+fn lines<'a>(table: &'a Table) -> impl Iterator<Item = Line<'a>> {
+    table.rows.iter().map(move |row| Line { table, row })
+}
+
+/// Single JSON document
+#[derive(Clone, Copy, Debug, Default)]
+pub struct JsonRenderer;
+
+impl Renderer for JsonRenderer {
+    fn render(&self, table: &Table, out: &mut dyn Write) -> Result<()> {
+        serde_json::to_writer_pretty(&mut *out, &document(table))?;
+        writeln!(out)?;
+        Ok(())
+    }
+
+    fn render_all(&self, tables: &[Table], out: &mut dyn Write) -> Result<()> {
+        let report = Report {
+            index: tables.iter().map(FileSummary::new).collect(),
+            files: tables.iter().map(document).collect(),
+        };
+        serde_json::to_writer_pretty(&mut *out, &report)?;
+        writeln!(out)?;
+        Ok(())
+    }
+}
+
+fn document<'a>(table: &'a Table) -> Document<'a> {
+    Document {
+        header: table.header(),
+        lines: lines(table).collect(),
+    }
+}
+
+/// JSON Lines, with a header followed by one record per line
+#[derive(Clone, Copy, Debug, Default)]
+pub struct JsonLinesRenderer;
+
+impl Renderer for JsonLinesRenderer {
+    fn render(&self, table: &Table, out: &mut dyn Write) -> Result<()> {
+        serde_json::to_writer(&mut *out, &table.header())?;
+        writeln!(out)?;
+        for line in lines(table) {
+            serde_json::to_writer(&mut *out, &line)?;
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,93 @@
+use std::io::{self, IsTerminal, Write};
+
+use anyhow::Result;
+use clap::ValueEnum;
+use serde::Deserialize;
+use yansi::Paint;
+
+use crate::table::Table;
+
+mod html;
+mod json;
+mod terminal;
+
+pub use self::{
+    html::HtmlRenderer,
+    json::{JsonLinesRenderer, JsonRenderer},
+    terminal::TerminalRenderer,
+};
+
+/// Writes an aggregated table of annotations in some output format
+pub trait Renderer {
+    fn render(&self, table: &Table, out: &mut dyn Write) -> Result<()>;
+
+    /// Writes tables for several files as a single report, by default writing
+    /// each table in turn
+    fn render_all(&self, tables: &[Table], out: &mut dyn Write) -> Result<()> {
+        for table in tables {
+            self.render(table, out)?;
+        }
+        Ok(())
+    }
+}
+
+/// Output formats for the annotated file
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    /// Table of annotations alongside the file, for reading in a terminal
+    #[default]
+    Terminal,
+    /// Single JSON document
+    Json,
+    /// JSON Lines, with a header followed by one record per line
+    Jsonl,
+    /// Standalone HTML page
+    Html,
+}
+
+impl Format {
+    pub fn renderer(self) -> Box<dyn Renderer> {
+        self.renderer_with(TerminalRenderer::default())
+    }
+
+    /// Renderer using the given options when writing terminal output, which
+    /// other formats ignore (HTML always shows legends in column tooltips)
+    pub fn renderer_with(self, terminal: TerminalRenderer) -> Box<dyn Renderer> {
+        match self {
+            Format::Terminal => Box::new(terminal),
+            Format::Json => Box::new(JsonRenderer),
+            Format::Jsonl => Box::new(JsonLinesRenderer),
+            Format::Html => Box::new(HtmlRenderer),
+        }
+    }
+}
+
+/// When to use colours and other styling in terminal output
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorChoice {
+    #[default]
+    Always,
+    /// Only when writing to a terminal and `NO_COLOR` is not set
+    Auto,
+    Never,
+}
+
+impl ColorChoice {
+    /// Enables or disables styling for all subsequent terminal output
+    pub fn apply(self) {
+        let enabled = match self {
+            ColorChoice::Always => true,
+            ColorChoice::Auto => {
+                io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none()
+            }
+            ColorChoice::Never => false,
+        };
+        if enabled {
+            Paint::enable();
+        } else {
+            Paint::disable();
+        }
+    }
+}
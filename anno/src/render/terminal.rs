@@ -0,0 +1,202 @@
+use std::io::Write;
+
+use anyhow::Result;
+use yansi::{Color, Paint};
+
+use crate::{
+    annotations::{Severity, Span},
+    render::Renderer,
+    table::{Column, DiffHighlight, Row, Table},
+};
+
+/// Table of annotations alongside the file, for reading in a terminal
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TerminalRenderer {
+    /// Whether to explain the symbols producers use above the header
+    pub legend: bool,
+    /// Whether to wrap annotations too wide for their column onto further
+    /// lines instead of cutting them short
+    pub wrap: bool,
+}
+
+impl Renderer for TerminalRenderer {
+    fn render(&self, table: &Table, out: &mut dyn Write) -> Result<()> {
+        // Lines are numbered in diff only mode, since most are left out
+        let number_width = if table.diff.only {
+            table.line_count.to_string().len()
+        } else {
+            0
+        };
+
+        // Explain any symbols producers use
+        if self.legend {
+            for column in &table.columns {
+                write_legend(column, out)?;
+            }
+        }
+
+        // Write header
+        if table.diff.only {
+            write!(out, "{:number_width$} | ", "")?;
+        }
+        for column in &table.columns {
+            write!(out, "{} | ", column.fit_header())?;
+        }
+        writeln!(out)?;
+
+        // Write file content with annotations added
+        for (index, row) in table.rows.iter().enumerate() {
+            if table.diff.only {
+                write_skipped(table, index, out)?;
+            }
+
+            // Fit each annotation to its column, possibly over several lines
+            let cells: Vec<Vec<String>> = table
+                .columns
+                .iter()
+                .enumerate()
+                .map(|(i, column)| {
+                    let text = table.cell_text(row, i);
+                    if self.wrap {
+                        column.wrap(&text)
+                    } else {
+                        vec![column.fit(&text)]
+                    }
+                })
+                .collect();
+            let height = cells.iter().map(Vec::len).max().unwrap_or(1);
+
+            for line in 0..height {
+                if table.diff.only {
+                    if line == 0 {
+                        write!(out, "{:>number_width$} | ", row.number)?;
+                    } else {
+                        write!(out, "{:number_width$} | ", "")?;
+                    }
+                }
+                for (i, column) in table.columns.iter().enumerate() {
+                    // Write current producer's annotation value, leaving the
+                    // column blank once it runs out of lines
+                    match cells[i].get(line) {
+                        Some(text) => write!(out, "{} | ", paint(table, row, i, text))?,
+                        None => write!(out, "{} | ", column.fit(""))?,
+                    }
+                }
+
+                // Write line from file being annotated (once), underlining
+                // any ranges
+                if line > 0 {
+                    writeln!(out)?;
+                    continue;
+                }
+                let spans: Vec<Span> = row
+                    .annotations
+                    .iter()
+                    .flat_map(|a| a.spans.iter().copied())
+                    .collect();
+                writeln!(out, "{}", underline_spans(row.text, &spans))?;
+            }
+        }
+        if table.diff.only {
+            write_skipped(table, table.rows.len(), out)?;
+        }
+
+        Ok(())
+    }
+
+    fn render_all(&self, tables: &[Table], out: &mut dyn Write) -> Result<()> {
+        for (i, table) in tables.iter().enumerate() {
+            if i > 0 {
+                writeln!(out)?;
+            }
+            write_heading(table.target, out)?;
+            self.render(table, out)?;
+        }
+        Ok(())
+    }
+}
+
+/// Styles text from the annotation in `column` (0-based) of `row`
+fn paint<'t>(table: &Table, row: &Row, column: usize, text: &'t str) -> Paint<&'t str> {
+    let mut painted_annotation = Paint::new(text);
+
+    // Colour annotations by severity, if supplied
+    painted_annotation = match row.annotations[column].severity {
+        Some(Severity::Warning) => painted_annotation.fg(Color::Yellow),
+        Some(Severity::Error) => painted_annotation.fg(Color::Red),
+        Some(Severity::Info) | None => painted_annotation,
+    };
+
+    // Highlight any differences in diff mode
+    match table.diff_highlight(row, column) {
+        Some(DiffHighlight::Before) => painted_annotation.fg(Color::Red).bold(),
+        Some(DiffHighlight::After) => painted_annotation.fg(Color::Green).bold(),
+        None => painted_annotation,
+    }
+}
+
+/// Writes a heading introducing one of several files
+fn write_heading(target: &str, out: &mut dyn Write) -> Result<()> {
+    writeln!(out, "{}", Paint::new(format!("==> {} <==", target)).bold())?;
+    Ok(())
+}
+
+/// Writes the meaning of each symbol a producer uses, if it gave any
+fn write_legend(column: &Column, out: &mut dyn Write) -> Result<()> {
+    let legend = &column.capabilities.legend;
+    if legend.is_empty() {
+        return Ok(());
+    }
+    let entries: Vec<String> = legend
+        .iter()
+        .map(|(symbol, meaning)| format!("{} = {}", symbol, meaning))
+        .collect();
+    let text = format!("{}: {}", column.producer.name(), entries.join(", "));
+    writeln!(out, "{}", Paint::new(text).dimmed())?;
+    Ok(())
+}
+
+/// Writes a separator for any lines left out before the row at `index`
+fn write_skipped(table: &Table, index: usize, out: &mut dyn Write) -> Result<()> {
+    if let Some(skipped) = table.skipped_before(index) {
+        let description = if skipped.start() == skipped.end() {
+            format!("line {} skipped", skipped.start())
+        } else {
+            format!("lines {}-{} skipped", skipped.start(), skipped.end())
+        };
+        writeln!(
+            out,
+            "{}",
+            Paint::new(format!("... ({})", description)).dimmed()
+        )?;
+    }
+    Ok(())
+}
+
+/// Underlines the columns of `line` covered by any of `spans`
+fn underline_spans(line: &str, spans: &[Span]) -> String {
+    if spans.is_empty() {
+        return line.to_string();
+    }
+    let mut underlined = String::new();
+    let mut segment = String::new();
+    let mut segment_covered = false;
+    let mut write_segment = |segment: &mut String, covered: bool| {
+        if covered {
+            underlined.push_str(&Paint::new(&segment).underline().to_string());
+        } else {
+            underlined.push_str(segment);
+        }
+        segment.clear();
+    };
+    for (i, c) in line.char_indices() {
+        let covered = spans.iter().any(|span| span.covers(i, c));
+        if covered != segment_covered {
+            write_segment(&mut segment, segment_covered);
+            segment_covered = covered;
+        }
+        segment.push(c);
+    }
+    write_segment(&mut segment, segment_covered);
+    underlined
+}
@@ -0,0 +1,241 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt, io,
+    process::Output,
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use duct::{cmd, Expression, Handle};
+use log::debug;
+
+use crate::{protocol::SUPPORTED_PROTOCOLS, Producer};
+
+/// How often to check on producers that are still running
+pub(crate) const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Limits on how long producers may run before they are killed
+#[derive(Debug, Default)]
+pub struct Timeouts {
+    /// Default limit applied to each producer
+    pub producer: Option<Duration>,
+    /// Limits for specific producers (by name), overriding `producer`
+    pub named: HashMap<String, Duration>,
+    /// Limit for all producers together
+    pub global: Option<Duration>,
+}
+
+impl Timeouts {
+    /// Limit that applies to the given producer, ignoring the global limit
+    fn for_producer(&self, producer: &Producer) -> Option<Duration> {
+        self.named.get(producer.name()).copied().or(self.producer)
+    }
+}
+
+/// Reasons a single producer failed to supply annotations
+#[derive(Debug)]
+enum Failure {
+    /// Command could not be started or waited on
+    Io(io::Error),
+    /// Command finished with a non-zero exit status
+    Status(std::process::ExitStatus),
+    /// Command did not finish in time and was killed
+    TimedOut(Duration),
+    /// Command output is not valid UTF-8
+    Encoding,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Failure::Io(e) => write!(f, "could not be run ({})", e),
+            Failure::Status(status) => write!(f, "exited unsuccessfully ({})", status),
+            Failure::TimedOut(limit) => {
+                write!(f, "timed out after {:?} and was killed", limit)
+            }
+            Failure::Encoding => write!(f, "produced output that is not valid UTF-8"),
+        }
+    }
+}
+
+/// A producer command that has been launched
+struct Running {
+    command_name: String,
+    handle: Option<Handle>,
+    started: Instant,
+    limit: Option<Duration>,
+    result: Option<Result<String, Failure>>,
+}
+
+/// Runs all producers concurrently, returning their raw output in the same
+/// order as `producers`
+///
+/// If any producer fails, waits for the others to finish (or time out) so
+/// that all failures can be reported together.
+pub(crate) fn run_producers(
+    producers: &[Producer],
+    target_path: &str,
+    target_name: &str,
+    target_line_count: usize,
+    env: &BTreeMap<String, String>,
+    timeouts: &Timeouts,
+) -> Result<Vec<String>> {
+    let started = Instant::now();
+
+    // Launch every producer before waiting on any of them
+    let mut running: Vec<Running> = producers
+        .iter()
+        .map(|producer| {
+            debug!("Producer: {:?}", producer);
+            let command_path = producer.command();
+            let command_name = command_path.display().to_string();
+            // Passed as a string, as `duct` never searches `PATH` for paths
+            let mut command = cmd!(command_path.into_os_string());
+            for (key, value) in env {
+                command = command.env(key, value);
+            }
+            // TODO: Should we pass both used-entered and absolute versions...?
+            command = command.env("ANNO_TARGET", target_path);
+            command = command.env("ANNO_TARGET_NAME", target_name);
+            command = command.env("ANNO_TARGET_LINES", target_line_count.to_string());
+            command = command.env("ANNO_PRODUCER", producer.name());
+            // TODO: Should this be absolute like `ANNO_TARGET`...?
+            command = command.env("ANNO_SOURCE", producer.source());
+            command = command.env("ANNO_PROTOCOLS", SUPPORTED_PROTOCOLS);
+            let params = producer.params();
+            for (key, values) in &params {
+                // Repeated keys are joined here, but kept separate in `ANNO_PARAMS`
+                command = command.env(param_var_name(key), values.join(","));
+            }
+            command = command.env("ANNO_PARAMS", serde_json::to_string(&params).unwrap());
+            debug!("Command: {:?}", command);
+            // Exit status is checked below so failures can be attributed
+            let handle = start_in_group(command.stdout_capture().unchecked());
+            let (handle, result) = match handle {
+                Ok(handle) => (Some(handle), None),
+                Err(e) => (None, Some(Err(Failure::Io(e)))),
+            };
+            Running {
+                command_name,
+                handle,
+                started: Instant::now(),
+                limit: timeouts.for_producer(producer),
+                result,
+            }
+        })
+        .collect();
+
+    // Poll until every producer has finished or been killed
+    while running.iter().any(|r| r.result.is_none()) {
+        let global_expired = timeouts
+            .global
+            .is_some_and(|limit| started.elapsed() >= limit);
+        for r in running.iter_mut().filter(|r| r.result.is_none()) {
+            let handle = r.handle.as_ref().unwrap();
+            match handle.try_wait() {
+                Ok(Some(output)) => {
+                    debug!(
+                        "`{}` finished after {:?}",
+                        r.command_name,
+                        r.started.elapsed()
+                    );
+                    r.result = Some(check_output(output));
+                    continue;
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    r.result = Some(Err(Failure::Io(e)));
+                    continue;
+                }
+            }
+            let producer_expired = r.limit.is_some_and(|limit| r.started.elapsed() >= limit);
+            if producer_expired || global_expired {
+                debug!("Killing `{}`", r.command_name);
+                kill_group(handle);
+                let limit = if producer_expired {
+                    r.limit.unwrap()
+                } else {
+                    timeouts.global.unwrap()
+                };
+                r.result = Some(Err(Failure::TimedOut(limit)));
+            }
+        }
+        if running.iter().any(|r| r.result.is_none()) {
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    // Report all failures together, otherwise return output in order
+    let mut failures = Vec::new();
+    let mut outputs = Vec::new();
+    for r in running {
+        match r.result.unwrap() {
+            Ok(data) => outputs.push(data),
+            Err(failure) => failures.push(format!("`{}` {}", r.command_name, failure)),
+        }
+    }
+    match failures.len() {
+        0 => Ok(outputs),
+        1 => Err(anyhow!("Annotation producer {}", failures[0])),
+        n => Err(anyhow!(
+            "{} annotation producers failed:\n  - {}",
+            n,
+            failures.join("\n  - ")
+        )),
+    }
+}
+
+/// Starts `command` in a process group of its own, so that any commands it
+/// runs in turn (such as a compiler) can be killed along with it
+pub(crate) fn start_in_group(command: Expression) -> io::Result<Handle> {
+    #[cfg(unix)]
+    let command = command.before_spawn(|command| {
+        use std::os::unix::process::CommandExt;
+
+        command.process_group(0);
+        Ok(())
+    });
+    command.start()
+}
+
+/// Kills a command started by [`start_in_group`] and everything else in its
+/// process group
+///
+/// Killing only the command itself is not enough, as waiting for its output
+/// blocks while any process it started still holds the output pipe open.
+pub(crate) fn kill_group(handle: &Handle) {
+    #[cfg(unix)]
+    for pid in handle.pids() {
+        // SAFETY: `kill` has no memory safety requirements
+        unsafe {
+            libc::kill(-(pid as libc::pid_t), libc::SIGKILL);
+        }
+    }
+    // Killing may fail if the process exited in the meantime, which is fine
+    // as its output is discarded either way
+    let _ = handle.kill();
+}
+
+/// Environment variable for a single query parameter, e.g. `ANNO_PARAM_FUNCTION`
+/// for `function`
+fn param_var_name(key: &str) -> String {
+    let key: String = key
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("ANNO_PARAM_{}", key)
+}
+
+fn check_output(output: &Output) -> Result<String, Failure> {
+    if !output.status.success() {
+        return Err(Failure::Status(output.status));
+    }
+    String::from_utf8(output.stdout.clone()).map_err(|_| Failure::Encoding)
+}
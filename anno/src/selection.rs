@@ -0,0 +1,183 @@
+use std::{fmt, ops::RangeInclusive};
+
+use anyhow::{anyhow, Result};
+
+/// Part of the file being annotated to show
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Selection {
+    /// Lines (1-based, inclusive)
+    Lines(RangeInclusive<usize>),
+    /// Lines of the named function's definition
+    Function(String),
+}
+
+impl Selection {
+    /// Parses a line range such as `120-180` or a single line such as `42`
+    pub fn parse_lines(input: &str) -> Result<Selection> {
+        let invalid = || {
+            anyhow!(
+                "`{}` is not a line range (expected `START-END` or `LINE`)",
+                input
+            )
+        };
+        let (start, end) = input.split_once('-').unwrap_or((input, input));
+        let start: usize = start.trim().parse().map_err(|_| invalid())?;
+        let end: usize = end.trim().parse().map_err(|_| invalid())?;
+        if start == 0 || start > end {
+            return Err(invalid());
+        }
+        Ok(Selection::Lines(start..=end))
+    }
+}
+
+/// Lines (1-based) selected from the file being annotated
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LineRanges(pub Vec<RangeInclusive<usize>>);
+
+impl LineRanges {
+    /// Resolves selections against the content of the file being annotated,
+    /// returning `None` when there are no selections
+    pub fn resolve(selections: &[Selection], content: &str) -> Result<Option<LineRanges>> {
+        if selections.is_empty() {
+            return Ok(None);
+        }
+        let line_count = content.lines().count();
+        let mut ranges = Vec::new();
+        for selection in selections {
+            let range = match selection {
+                Selection::Lines(range) => range.clone(),
+                Selection::Function(name) => find_function(content, name).ok_or_else(|| {
+                    anyhow!(
+                        "Unable to find a definition of function `{}` in the file being annotated",
+                        name
+                    )
+                })?,
+            };
+            // Ignore any part of the range beyond the end of the file
+            if *range.start() <= line_count {
+                ranges.push(*range.start()..=(*range.end()).min(line_count));
+            }
+        }
+        ranges.sort_by_key(|range| *range.start());
+        Ok(Some(LineRanges(ranges)))
+    }
+
+    pub fn contains(&self, line: usize) -> bool {
+        self.0.iter().any(|range| range.contains(&line))
+    }
+}
+
+/// Written as comma-separated ranges, e.g. `120-180,200-200`
+impl fmt::Display for LineRanges {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ranges: Vec<String> = self
+            .0
+            .iter()
+            .map(|range| format!("{}-{}", range.start(), range.end()))
+            .collect();
+        write!(f, "{}", ranges.join(","))
+    }
+}
+
+/// Finds the lines of a C function definition using a lightweight heuristic
+///
+/// Tracks brace depth (ignoring comments, strings, and preprocessor lines) to
+/// find `name(` at the top level followed by a `{`. The definition starts at
+/// the first line of its declaration (so any return type on a previous line
+/// is included) and ends at the matching `}`.
+pub fn find_function(content: &str, name: &str) -> Option<RangeInclusive<usize>> {
+    let mut depth = 0usize;
+    let mut in_block_comment = false;
+    // First line of the current top-level declaration
+    let mut declaration_start: Option<usize> = None;
+    // Whether the current top-level declaration names the function
+    let mut names_function = false;
+    // Start of the function body's declaration while inside it
+    let mut body_start: Option<usize> = None;
+
+    for (index, line) in content.lines().enumerate() {
+        let number = index + 1;
+        if !in_block_comment && line.trim_start().starts_with('#') {
+            continue;
+        }
+        let chars: Vec<char> = line.chars().collect();
+        let mut i = 0;
+        while i < chars.len() {
+            let c = chars[i];
+            let next = chars.get(i + 1).copied();
+            if in_block_comment {
+                if c == '*' && next == Some('/') {
+                    in_block_comment = false;
+                    i += 1;
+                }
+                i += 1;
+                continue;
+            }
+            match c {
+                '/' if next == Some('/') => break,
+                '/' if next == Some('*') => {
+                    in_block_comment = true;
+                    i += 1;
+                }
+                '"' | '\'' => {
+                    // Skip to the closing quote, allowing for escapes
+                    i += 1;
+                    while i < chars.len() && chars[i] != c {
+                        if chars[i] == '\\' {
+                            i += 1;
+                        }
+                        i += 1;
+                    }
+                }
+                '{' => {
+                    if depth == 0 && names_function {
+                        body_start = declaration_start;
+                    }
+                    depth += 1;
+                }
+                '}' => {
+                    depth = depth.saturating_sub(1);
+                    if depth == 0 {
+                        if let Some(start) = body_start {
+                            return Some(start..=number);
+                        }
+                        declaration_start = None;
+                        names_function = false;
+                    }
+                }
+                ';' if depth == 0 => {
+                    declaration_start = None;
+                    names_function = false;
+                }
+                _ if depth == 0 && !c.is_whitespace() => {
+                    declaration_start.get_or_insert(number);
+                    if is_identifier_start(c) && (i == 0 || !is_identifier_char(chars[i - 1])) {
+                        let identifier: String = chars[i..]
+                            .iter()
+                            .take_while(|&&c| is_identifier_char(c))
+                            .collect();
+                        let rest = chars[i + identifier.chars().count()..]
+                            .iter()
+                            .find(|c| !c.is_whitespace());
+                        if identifier == name && rest.is_none_or(|&c| c == '(') {
+                            names_function = true;
+                        }
+                        i += identifier.chars().count();
+                        continue;
+                    }
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+    }
+    None
+}
+
+fn is_identifier_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_'
+}
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
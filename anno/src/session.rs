@@ -0,0 +1,489 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    ffi::OsStr,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context, Result};
+use log::{debug, info};
+use path_absolutize::*;
+use serde_json::json;
+
+use crate::{
+    annotations::{Annotation, Annotations, DEFAULT_MAX_WIDTH},
+    cache::{digest, Cache},
+    describe::{check_producers, Capabilities, ValueKind},
+    protocol::{covers_targets, parse_output, parse_targets_output},
+    run::{run_producers, Timeouts},
+    selection::{find_function, LineRanges, Selection},
+    table::{DiffOptions, Table},
+    validate::MismatchPolicy,
+    Producer,
+};
+
+/// Runs a set of producers over files and aggregates their annotations
+#[derive(Debug, Default)]
+pub struct Session {
+    pub producers: Vec<Producer>,
+    /// Limits on how long producers may run
+    pub timeouts: Timeouts,
+    /// What to do when a producer's output does not cover the file exactly
+    pub on_mismatch: MismatchPolicy,
+    /// Extra environment variables for every producer
+    pub env: BTreeMap<String, String>,
+    /// Cap on the width of each producer's column, in terminal columns (the
+    /// producer's preferred width, or 30, if not set)
+    pub max_width: Option<usize>,
+    /// Caps for specific producers' columns (by name), overriding `max_width`
+    pub column_widths: HashMap<String, usize>,
+    /// Parts of the file to show, or the whole file if empty
+    pub selections: Vec<Selection>,
+    /// Where producer output is reused from and stored, if anywhere
+    pub cache: Option<Cache>,
+    /// Capabilities declared by each producer, in the same order as
+    /// `producers` (with defaults for any missing)
+    pub capabilities: Vec<Capabilities>,
+    /// Inherited environment variables each producer says it reads, in the
+    /// same order as `producers` (`None` where any may affect its output)
+    pub reads_env: Vec<Option<Vec<String>>>,
+}
+
+/// JSON file listing every target, named by `ANNO_TARGETS`
+struct TargetsFile {
+    path: PathBuf,
+    /// Digest of the listing and the content of each target
+    digest: String,
+}
+
+/// Annotations from every producer in a session for a single file
+#[derive(Debug)]
+pub struct Annotated<'s> {
+    /// Path to the annotated file, as given to the session
+    pub target: String,
+    /// Content of the annotated file
+    pub content: String,
+    pub producers: &'s [Producer],
+    /// Annotations from each producer, in the same order as `producers`
+    pub annotations: Vec<Annotations>,
+    /// Lines selected from the file, or `None` for the whole file
+    pub lines: Option<LineRanges>,
+}
+
+impl Session {
+    pub fn new(producers: Vec<Producer>) -> Session {
+        Session {
+            producers,
+            ..Default::default()
+        }
+    }
+
+    /// Asks each producer to describe itself, checking that it is given what
+    /// it needs and keeping the capabilities and variables it declares
+    pub fn describe_producers(&mut self) -> Result<()> {
+        let descriptions = check_producers(&self.producers, self.cache.as_ref())?;
+        self.reads_env = descriptions
+            .iter()
+            .map(|description| description.as_ref()?.env.clone())
+            .collect();
+        self.capabilities = descriptions
+            .into_iter()
+            .map(|description| description.map(|d| d.capabilities).unwrap_or_default())
+            .collect();
+        Ok(())
+    }
+
+    /// Column (0-based) to use as the diff baseline when none is chosen,
+    /// which is the first producer whose annotations can be diffed
+    pub fn default_baseline(&self) -> usize {
+        (0..self.producers.len())
+            .find(|&index| {
+                self.capabilities
+                    .get(index)
+                    .is_none_or(|capabilities| capabilities.diffable)
+            })
+            .unwrap_or_default()
+    }
+
+    /// Reads the file at `target` and runs all producers over it
+    pub fn annotate(&self, target: &Path) -> Result<Annotated<'_>> {
+        let target_path = target.absolutize()?;
+        let content = fs::read_to_string(&target_path).with_context(|| {
+            format!("Unable to read file to be annotated ({})", target.display())
+        })?;
+        self.annotate_at(target, &target_path, content)
+    }
+
+    /// Runs all producers over the given content for a file at `target`,
+    /// such as an unsaved editor buffer
+    ///
+    /// The content is written to a temporary file with the same name for
+    /// producers to read, so it need not match the file at `target` (which
+    /// need not exist).
+    pub fn annotate_content(&self, target: &Path, content: String) -> Result<Annotated<'_>> {
+        let dir = tempfile::Builder::new()
+            .prefix("anno-")
+            .tempdir()
+            .context("Unable to create temporary directory")?;
+        let content_path = dir
+            .path()
+            .join(target.file_name().unwrap_or(OsStr::new("stdin")));
+        fs::write(&content_path, &content).with_context(|| {
+            format!(
+                "Unable to write content to be annotated ({})",
+                content_path.display()
+            )
+        })?;
+        self.annotate_at(target, &content_path, content)
+    }
+
+    /// Reads each file in `targets` and runs all producers over them
+    ///
+    /// Each producer is run once with every target listed in a JSON file named
+    /// by `ANNO_TARGETS`, and is otherwise told about the first target as if
+    /// it were the only one. Producers which only annotate the first target
+    /// are then run again for each of the others. When selecting functions,
+    /// each need only be found in one of the files, and files without any
+    /// selected lines are left out.
+    pub fn annotate_all(&self, targets: &[PathBuf]) -> Result<Vec<Annotated<'_>>> {
+        if let [target] = targets {
+            return Ok(vec![self.annotate(target)?]);
+        }
+
+        let mut files = Vec::new();
+        for target in targets {
+            let target_path = target.absolutize()?.into_owned();
+            let content = fs::read_to_string(&target_path).with_context(|| {
+                format!("Unable to read file to be annotated ({})", target.display())
+            })?;
+            let lines = self.select_lines(&content)?;
+            if lines.as_ref().is_some_and(|lines| lines.0.is_empty()) {
+                debug!("No lines selected in {}", target.display());
+                continue;
+            }
+            files.push((target, target_path, content, lines));
+        }
+        for selection in &self.selections {
+            if let Selection::Function(name) = selection {
+                if !files
+                    .iter()
+                    .any(|(_, _, content, _)| find_function(content, name).is_some())
+                {
+                    return Err(anyhow!(
+                        "Unable to find a definition of function `{}` in the files being annotated",
+                        name
+                    ));
+                }
+            }
+        }
+        let Some((_, first_path, first_content, first_lines)) = files.first() else {
+            return Ok(Vec::new());
+        };
+
+        // Describe every target for producers able to annotate them together
+        let dir = tempfile::Builder::new()
+            .prefix("anno-")
+            .tempdir()
+            .context("Unable to create temporary directory")?;
+        let targets_path = dir.path().join("targets.json");
+        let description: Vec<_> = files
+            .iter()
+            .map(|(_, target_path, content, lines)| {
+                json!({
+                    "path": target_path,
+                    "lines": content.lines().count(),
+                    "line_ranges": lines.as_ref().map(LineRanges::to_string),
+                })
+            })
+            .collect();
+        fs::write(&targets_path, serde_json::to_string(&description)?).with_context(|| {
+            format!(
+                "Unable to write targets to be annotated ({})",
+                targets_path.display()
+            )
+        })?;
+        let contents: Vec<String> = files
+            .iter()
+            .map(|(_, _, content, _)| digest(content.as_bytes()))
+            .collect();
+        let targets_file = TargetsFile {
+            path: targets_path,
+            digest: digest(json!([description, contents]).to_string().as_bytes()),
+        };
+
+        let indices: Vec<usize> = (0..self.producers.len()).collect();
+        let outputs = self.run_raw(
+            &indices,
+            first_path,
+            first_path,
+            first_content,
+            first_lines.as_ref(),
+            Some(&targets_file),
+        )?;
+        let mut annotations: Vec<Vec<Option<Annotations>>> = files
+            .iter()
+            .map(|_| (0..self.producers.len()).map(|_| None).collect())
+            .collect();
+        let mut reruns = Vec::new();
+        for (index, data) in outputs.into_iter().enumerate() {
+            let producer = self.producers[index].name();
+            if covers_targets(&data) {
+                let targets: Vec<(&str, usize)> = files
+                    .iter()
+                    .map(|(_, target_path, content, _)| {
+                        (target_path.to_str().unwrap(), content.lines().count())
+                    })
+                    .collect();
+                let parsed = parse_targets_output(producer, &data, &targets, self.on_mismatch)?;
+                for (file, lines) in parsed.into_iter().enumerate() {
+                    annotations[file][index] = Some(self.wrap(index, lines));
+                }
+            } else {
+                let line_count = first_content.lines().count();
+                let lines = parse_output(producer, data, line_count, self.on_mismatch)?;
+                annotations[0][index] = Some(self.wrap(index, lines));
+                reruns.push(index);
+            }
+        }
+        if !reruns.is_empty() {
+            debug!("Running again for each target: {:?}", reruns);
+            for (file, (_, target_path, content, lines)) in files.iter().enumerate().skip(1) {
+                let rerun = self.run(&reruns, target_path, target_path, content, lines.as_ref())?;
+                for (&index, produced) in reruns.iter().zip(rerun) {
+                    annotations[file][index] = Some(produced);
+                }
+            }
+        }
+
+        Ok(files
+            .into_iter()
+            .zip(annotations)
+            .map(|((target, _, content, lines), annotations)| Annotated {
+                target: target.to_string_lossy().into_owned(),
+                content,
+                producers: &self.producers,
+                annotations: annotations.into_iter().map(Option::unwrap).collect(),
+                lines,
+            })
+            .collect())
+    }
+
+    /// Lines of `content` selected by this session, where functions not
+    /// defined in `content` select nothing
+    fn select_lines(&self, content: &str) -> Result<Option<LineRanges>> {
+        if self.selections.is_empty() {
+            return Ok(None);
+        }
+        let found: Vec<Selection> = self
+            .selections
+            .iter()
+            .filter(|selection| match selection {
+                Selection::Function(name) => find_function(content, name).is_some(),
+                Selection::Lines(_) => true,
+            })
+            .cloned()
+            .collect();
+        Ok(Some(
+            LineRanges::resolve(&found, content)?.unwrap_or_default(),
+        ))
+    }
+
+    /// Runs all producers over `content`, which producers can read from
+    /// `content_path`
+    fn annotate_at(
+        &self,
+        target: &Path,
+        content_path: &Path,
+        content: String,
+    ) -> Result<Annotated<'_>> {
+        let target_path = target.absolutize()?;
+        debug!("Lines: {}", content.lines().count());
+
+        // Let producers skip work for lines that will not be shown
+        let lines = LineRanges::resolve(&self.selections, &content)?;
+        let producers: Vec<usize> = (0..self.producers.len()).collect();
+        let annotations = self.run(
+            &producers,
+            content_path,
+            &target_path,
+            &content,
+            lines.as_ref(),
+        )?;
+
+        Ok(Annotated {
+            target: target.to_string_lossy().into_owned(),
+            content,
+            producers: &self.producers,
+            annotations,
+            lines,
+        })
+    }
+
+    /// Runs some producers (by index) again over a file annotated by this
+    /// session, replacing their annotations
+    ///
+    /// Useful when only the data sources of those producers have changed. The
+    /// file itself must be unchanged on disk.
+    pub fn reannotate(&self, annotated: &mut Annotated<'_>, producers: &[usize]) -> Result<()> {
+        let target_path = Path::new(&annotated.target).absolutize()?;
+        let annotations = self.run(
+            producers,
+            &target_path,
+            &target_path,
+            &annotated.content,
+            annotated.lines.as_ref(),
+        )?;
+        for (&index, annotations) in producers.iter().zip(annotations) {
+            annotated.annotations[index] = annotations;
+        }
+        Ok(())
+    }
+
+    /// Runs the producers at `indices` concurrently, returning their
+    /// annotations in the same order
+    fn run(
+        &self,
+        indices: &[usize],
+        content_path: &Path,
+        target_path: &Path,
+        content: &str,
+        lines: Option<&LineRanges>,
+    ) -> Result<Vec<Annotations>> {
+        let line_count = content.lines().count();
+        let produced_data =
+            self.run_raw(indices, content_path, target_path, content, lines, None)?;
+        let mut produced_annotations = Vec::new();
+        for (&index, data) in indices.iter().zip(produced_data) {
+            // Ensure we have an annotation for every target line
+            let producer = self.producers[index].name();
+            let lines = parse_output(producer, data, line_count, self.on_mismatch)?;
+            produced_annotations.push(self.wrap(index, lines));
+        }
+        Ok(produced_annotations)
+    }
+
+    /// Runs the producers at `indices` concurrently, returning their raw
+    /// output in the same order
+    ///
+    /// Output is reused from the cache where possible, and otherwise stored in
+    /// it once every producer has succeeded.
+    fn run_raw(
+        &self,
+        indices: &[usize],
+        content_path: &Path,
+        target_path: &Path,
+        content: &str,
+        lines: Option<&LineRanges>,
+        targets: Option<&TargetsFile>,
+    ) -> Result<Vec<String>> {
+        let mut env = self.env.clone();
+        if let Some(lines) = lines {
+            debug!("Selected lines: {}", lines);
+            env.insert("ANNO_LINE_RANGES".to_string(), lines.to_string());
+        }
+
+        // Content may be in a temporary file, so its path is left out
+        let inputs = json!({
+            "target": target_path,
+            "content": digest(content.as_bytes()),
+            "env": env,
+            "targets": targets.map(|targets| &targets.digest),
+        });
+        let keys: Vec<Option<String>> = indices
+            .iter()
+            .map(|&index| {
+                let reads_env = self.reads_env.get(index).and_then(Option::as_deref);
+                self.cache
+                    .as_ref()?
+                    .key(&self.producers[index], reads_env, &inputs)
+            })
+            .collect();
+        let mut outputs: Vec<Option<String>> = indices
+            .iter()
+            .zip(&keys)
+            .map(|(&index, key)| {
+                let data = self.cache.as_ref()?.get(key.as_ref()?)?;
+                info!("Using cached output for `{}`", self.producers[index].uri());
+                Some(data)
+            })
+            .collect();
+        let missing: Vec<usize> = (0..indices.len())
+            .filter(|&i| outputs[i].is_none())
+            .collect();
+        if missing.is_empty() {
+            return Ok(outputs.into_iter().flatten().collect());
+        }
+
+        if let Some(targets) = targets {
+            env.insert(
+                "ANNO_TARGETS".to_string(),
+                targets.path.to_str().unwrap().to_string(),
+            );
+        }
+
+        // Collect output from each producer, running them all concurrently
+        let producers: Vec<Producer> = missing
+            .iter()
+            .map(|&i| self.producers[indices[i]].clone())
+            .collect();
+        let produced_data = run_producers(
+            &producers,
+            content_path.to_str().unwrap(),
+            target_path.to_str().unwrap(),
+            content.lines().count(),
+            &env,
+            &self.timeouts,
+        )?;
+        for (&i, data) in missing.iter().zip(produced_data) {
+            if let (Some(cache), Some(key)) = (&self.cache, &keys[i]) {
+                cache.put(key, &data);
+            }
+            outputs[i] = Some(data);
+        }
+        Ok(outputs.into_iter().flatten().collect())
+    }
+
+    /// Collects annotations from the producer at `index`, applying the
+    /// capabilities it declared
+    fn wrap(&self, index: usize, mut lines: Vec<Annotation>) -> Annotations {
+        let capabilities = self.capabilities.get(index).cloned().unwrap_or_default();
+        if capabilities.kind == Some(ValueKind::Set) {
+            // Values from producers using the lines protocol become sets of
+            // whitespace-separated tokens
+            for annotation in lines.iter_mut().filter(|a| a.elements.is_none()) {
+                let set = Annotation::set(annotation.tokens().map(String::from).collect());
+                annotation.value = set.value;
+                annotation.elements = set.elements;
+            }
+        }
+        let cap = self
+            .column_widths
+            .get(self.producers[index].name())
+            .copied()
+            .or(self.max_width)
+            .or(capabilities.width)
+            .unwrap_or(DEFAULT_MAX_WIDTH);
+        let mut annotations = Annotations::with_max_width(lines, cap);
+        annotations.capabilities = capabilities;
+        debug!("Annotations: {:?}", annotations);
+        annotations
+    }
+}
+
+impl Annotated<'_> {
+    /// Aggregates annotations from all producers line by line, keeping only
+    /// the selected lines
+    pub fn table(&self, diff: DiffOptions) -> Table<'_> {
+        let mut table = Table::new(
+            &self.target,
+            &self.content,
+            self.producers,
+            &self.annotations,
+            diff,
+        );
+        if let Some(lines) = &self.lines {
+            table.rows.retain(|row| lines.contains(row.number));
+        }
+        table
+    }
+}
@@ -0,0 +1,317 @@
+use std::{collections::BTreeSet, io::Write};
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::{annotations::Annotation, table::Table};
+
+/// Statistics describing the annotations from each producer and how they
+/// compare with the baseline producer
+#[derive(Debug, Serialize)]
+pub struct Summary {
+    /// Number of lines summarised
+    pub lines: usize,
+    pub producers: Vec<ProducerSummary>,
+    /// Comparison of each compared column with the baseline, as selected by
+    /// the table's diff options
+    pub comparisons: Vec<Comparison>,
+}
+
+/// Brief statistics for one of several annotated files, used to index
+/// reports covering all of them
+#[derive(Debug, Serialize)]
+pub struct FileSummary {
+    pub target: String,
+    pub line_count: usize,
+    pub producers: Vec<ProducerSummary>,
+}
+
+/// Counts for a single producer's column
+#[derive(Debug, Serialize)]
+pub struct ProducerSummary {
+    pub key: String,
+    /// Lines with a non-blank annotation
+    pub annotated_lines: usize,
+    /// Total number of tokens (such as variable names) across all lines
+    pub elements: usize,
+}
+
+/// How one producer's annotations compare with the baseline
+#[derive(Debug, Serialize)]
+pub struct Comparison {
+    pub baseline: String,
+    pub compared: String,
+    /// Overlap between annotated lines
+    pub lines: Overlap,
+    /// Overlap between tokens on each line, when either column is a set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub elements: Option<Overlap>,
+    /// For each token in the baseline, how many of its lines also have it in
+    /// the compared column, when either column is a set
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub coverage: Vec<Coverage>,
+}
+
+/// Agreement between two collections, treating the baseline as the
+/// reference
+#[derive(Debug, Default, Serialize)]
+pub struct Overlap {
+    pub both: usize,
+    pub only_baseline: usize,
+    pub only_compared: usize,
+    /// Size of the intersection over size of the union
+    pub jaccard: Option<f64>,
+    /// Fraction of the compared collection also in the baseline
+    pub precision: Option<f64>,
+    /// Fraction of the baseline also in the compared collection
+    pub recall: Option<f64>,
+}
+
+/// How often a single baseline token is also found in the compared column
+#[derive(Debug, Serialize)]
+pub struct Coverage {
+    pub element: String,
+    /// Lines where the baseline has this token
+    pub baseline_lines: usize,
+    /// Lines where both the baseline and the compared column have it
+    pub covered_lines: usize,
+    pub ratio: f64,
+}
+
+impl Overlap {
+    fn add(&mut self, both: usize, only_baseline: usize, only_compared: usize) {
+        self.both += both;
+        self.only_baseline += only_baseline;
+        self.only_compared += only_compared;
+    }
+
+    fn finish(mut self) -> Overlap {
+        let both = self.both as f64;
+        self.jaccard = ratio(both, self.both + self.only_baseline + self.only_compared);
+        self.precision = ratio(both, self.both + self.only_compared);
+        self.recall = ratio(both, self.both + self.only_baseline);
+        self
+    }
+}
+
+fn ratio(numerator: f64, denominator: usize) -> Option<f64> {
+    if denominator == 0 {
+        None
+    } else {
+        Some(numerator / denominator as f64)
+    }
+}
+
+fn is_marked(annotation: &Annotation) -> bool {
+    !annotation.value.trim().is_empty()
+}
+
+impl Summary {
+    /// Summarises the lines included in `table`
+    pub fn new(table: &Table) -> Summary {
+        let producers = producer_summaries(table);
+
+        let baseline = table.diff.baseline;
+        let comparisons = if baseline < table.columns.len() {
+            table
+                .compared_columns()
+                .iter()
+                .map(|&compared| Comparison::new(table, baseline, compared))
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        Summary {
+            lines: table.rows.len(),
+            producers,
+            comparisons,
+        }
+    }
+
+    /// Writes the summary as plain text tables
+    pub fn write_text(&self, out: &mut dyn Write) -> Result<()> {
+        writeln!(out, "{} lines", self.lines)?;
+        writeln!(out)?;
+
+        let key_width = self
+            .producers
+            .iter()
+            .map(|producer| producer.key.len())
+            .max()
+            .unwrap_or_default()
+            .max("Producer".len());
+        writeln!(
+            out,
+            "{:key_width$} | {:>5} | {:>8}",
+            "Producer", "Lines", "Elements"
+        )?;
+        for producer in &self.producers {
+            writeln!(
+                out,
+                "{:key_width$} | {:>5} | {:>8}",
+                producer.key, producer.annotated_lines, producer.elements
+            )?;
+        }
+
+        for comparison in &self.comparisons {
+            writeln!(out)?;
+            writeln!(
+                out,
+                "{} compared with {}",
+                comparison.compared, comparison.baseline
+            )?;
+            writeln!(
+                out,
+                "{:8} | {:>5} | {:>13} | {:>13} | {:>7} | {:>9} | {:>6}",
+                "", "Both", "Only baseline", "Only compared", "Jaccard", "Precision", "Recall"
+            )?;
+            write_overlap(out, "Lines", &comparison.lines)?;
+            if let Some(elements) = &comparison.elements {
+                write_overlap(out, "Elements", elements)?;
+            }
+            if !comparison.coverage.is_empty() {
+                let element_width = comparison
+                    .coverage
+                    .iter()
+                    .map(|coverage| coverage.element.len())
+                    .max()
+                    .unwrap_or_default()
+                    .max("Element".len());
+                writeln!(out)?;
+                writeln!(
+                    out,
+                    "{:element_width$} | {:>7} | {:>5}",
+                    "Element", "Covered", "Ratio"
+                )?;
+                for coverage in &comparison.coverage {
+                    writeln!(
+                        out,
+                        "{:element_width$} | {:>7} | {:>5.3}",
+                        coverage.element,
+                        format!("{}/{}", coverage.covered_lines, coverage.baseline_lines),
+                        coverage.ratio
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FileSummary {
+    /// Summarises the lines included in `table`
+    pub fn new(table: &Table) -> FileSummary {
+        FileSummary {
+            target: table.target.to_string(),
+            line_count: table.line_count,
+            producers: producer_summaries(table),
+        }
+    }
+}
+
+fn producer_summaries(table: &Table) -> Vec<ProducerSummary> {
+    table
+        .columns
+        .iter()
+        .enumerate()
+        .map(|(i, column)| ProducerSummary {
+            key: column.key.clone(),
+            annotated_lines: table
+                .rows
+                .iter()
+                .filter(|row| is_marked(row.annotations[i]))
+                .count(),
+            elements: table
+                .rows
+                .iter()
+                .map(|row| row.annotations[i].tokens().count())
+                .sum(),
+        })
+        .collect()
+}
+
+fn write_overlap(out: &mut dyn Write, label: &str, overlap: &Overlap) -> Result<()> {
+    let format_ratio = |ratio: Option<f64>| match ratio {
+        Some(ratio) => format!("{:.3}", ratio),
+        None => "-".to_string(),
+    };
+    writeln!(
+        out,
+        "{:8} | {:>5} | {:>13} | {:>13} | {:>7} | {:>9} | {:>6}",
+        label,
+        overlap.both,
+        overlap.only_baseline,
+        overlap.only_compared,
+        format_ratio(overlap.jaccard),
+        format_ratio(overlap.precision),
+        format_ratio(overlap.recall)
+    )?;
+    Ok(())
+}
+
+impl Comparison {
+    fn new(table: &Table, baseline: usize, compared: usize) -> Comparison {
+        let mut lines = Overlap::default();
+        let mut elements = Overlap::default();
+        // Baseline tokens in the order first seen, with line counts
+        let mut coverage: Vec<Coverage> = Vec::new();
+        let mut has_sets = false;
+
+        for row in &table.rows {
+            let baseline_annotation = row.annotations[baseline];
+            let compared_annotation = row.annotations[compared];
+            match (
+                is_marked(baseline_annotation),
+                is_marked(compared_annotation),
+            ) {
+                (true, true) => lines.add(1, 0, 0),
+                (true, false) => lines.add(0, 1, 0),
+                (false, true) => lines.add(0, 0, 1),
+                (false, false) => {}
+            }
+
+            if baseline_annotation.elements.is_some() || compared_annotation.elements.is_some() {
+                has_sets = true;
+            }
+            let baseline_tokens: BTreeSet<&str> = baseline_annotation.tokens().collect();
+            let compared_tokens: BTreeSet<&str> = compared_annotation.tokens().collect();
+            let both = baseline_tokens.intersection(&compared_tokens).count();
+            elements.add(
+                both,
+                baseline_tokens.len() - both,
+                compared_tokens.len() - both,
+            );
+            for token in baseline_annotation.tokens() {
+                let index = match coverage.iter().position(|c| c.element == token) {
+                    Some(index) => index,
+                    None => {
+                        coverage.push(Coverage {
+                            element: token.to_string(),
+                            baseline_lines: 0,
+                            covered_lines: 0,
+                            ratio: 0.0,
+                        });
+                        coverage.len() - 1
+                    }
+                };
+                coverage[index].baseline_lines += 1;
+                if compared_tokens.contains(token) {
+                    coverage[index].covered_lines += 1;
+                }
+            }
+        }
+
+        for c in &mut coverage {
+            c.ratio = c.covered_lines as f64 / c.baseline_lines as f64;
+        }
+
+        Comparison {
+            baseline: table.columns[baseline].key.clone(),
+            compared: table.columns[compared].key.clone(),
+            lines: lines.finish(),
+            elements: has_sets.then(|| elements.finish()),
+            coverage: if has_sets { coverage } else { Vec::new() },
+        }
+    }
+}
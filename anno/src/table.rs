@@ -0,0 +1,407 @@
+use std::{borrow::Cow, collections::BTreeSet, fmt, iter, ops::RangeInclusive};
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+
+use crate::{
+    annotations::{Annotation, Annotations},
+    describe::{Align, Capabilities, ValueKind},
+    width, Producer,
+};
+
+/// Which lines to compare and keep when diffing producers
+#[derive(Clone, Debug, Default)]
+pub struct DiffOptions {
+    /// Highlight differences from the baseline column
+    pub highlight: bool,
+    /// Only keep lines with differences from the baseline column
+    pub only: bool,
+    /// Column (0-based) that other columns are compared against
+    pub baseline: usize,
+    /// Columns (0-based) to compare against the baseline, or all other
+    /// columns if not set
+    pub columns: Option<Vec<usize>>,
+    /// Lines to keep around each line with differences in diff only mode
+    pub context: usize,
+}
+
+impl DiffOptions {
+    /// Checks that all referenced columns exist
+    ///
+    /// Without any producers there is nothing to diff, so the baseline is
+    /// only checked when `baseline_chosen` (rather than left as the default).
+    pub fn validate(&self, column_count: usize, baseline_chosen: bool) -> Result<()> {
+        let out_of_range = iter::once(self.baseline)
+            .filter(|_| column_count > 0 || baseline_chosen)
+            .chain(self.columns.iter().flatten().copied())
+            .find(|&column| column >= column_count);
+        if let Some(column) = out_of_range {
+            return Err(anyhow!(
+                "Cannot diff column {}, as there are only {} producers (columns are numbered from 0)",
+                column,
+                column_count
+            ));
+        }
+        Ok(())
+    }
+
+    /// Columns compared against the baseline, which never includes the
+    /// baseline itself
+    pub fn compared_columns(&self, column_count: usize) -> Vec<usize> {
+        match &self.columns {
+            Some(columns) => columns
+                .iter()
+                .copied()
+                .filter(|&column| column != self.baseline && column < column_count)
+                .collect(),
+            None => (0..column_count)
+                .filter(|&column| column != self.baseline)
+                .collect(),
+        }
+    }
+}
+
+/// How a cell is highlighted in diff mode
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DiffHighlight {
+    /// Baseline annotation on a line where some column differs
+    Before,
+    /// Annotation that differs from the baseline
+    After,
+}
+
+/// Aggregated annotations from all producers for the file being annotated,
+/// shared by all output formats
+#[derive(Debug)]
+pub struct Table<'a> {
+    /// Path to the file being annotated, as given by the user
+    pub target: &'a str,
+    /// Total number of lines in the file being annotated
+    pub line_count: usize,
+    pub columns: Vec<Column<'a>>,
+    /// Lines to display, after any filtering
+    pub rows: Vec<Row<'a>>,
+    pub diff: DiffOptions,
+    /// Columns compared against the baseline, as given by
+    /// [`Table::compared_columns`]
+    compared_columns: Vec<usize>,
+}
+
+/// Annotations from a single producer
+#[derive(Debug)]
+pub struct Column<'a> {
+    pub producer: &'a Producer,
+    /// Identifies this column in machine-readable output, which is the
+    /// producer name unless several columns share it
+    pub key: String,
+    /// Maximum width of annotation values in this column
+    pub max_width: usize,
+    /// How annotations in this column should be compared and displayed
+    pub capabilities: &'a Capabilities,
+}
+
+impl Column<'_> {
+    /// Pads `text` to the column width, aligned as the producer prefers, or
+    /// cuts it short with an ellipsis if it does not fit
+    pub fn fit(&self, text: &str) -> String {
+        let truncated = width::truncate(text, self.max_width);
+        width::pad(&truncated, self.max_width, self.capabilities.alignment())
+    }
+
+    /// Pads the producer name to the column width for use as a header, or
+    /// cuts it short with an ellipsis if it does not fit
+    pub fn fit_header(&self) -> String {
+        let name = width::truncate(self.producer.name(), self.max_width);
+        width::pad(&name, self.max_width, Align::Left)
+    }
+
+    /// Splits `text` into lines fitting the column width instead of cutting
+    /// it short, each padded as [`Column::fit`] does
+    pub fn wrap(&self, text: &str) -> Vec<String> {
+        width::wrap(text, self.max_width)
+            .iter()
+            .map(|line| self.fit(line))
+            .collect()
+    }
+}
+
+/// Annotations from all producers for one line of the file being annotated
+#[derive(Debug)]
+pub struct Row<'a> {
+    /// Line number (1-based)
+    pub number: usize,
+    /// Text of the line from the file being annotated
+    pub text: &'a str,
+    /// Annotation from each producer, in the same order as the columns
+    pub annotations: Vec<&'a Annotation>,
+    /// Whether each column is compared against the baseline and disagrees
+    /// with it on this line
+    pub differing: Vec<bool>,
+    /// Whether any compared column disagrees with the baseline on this line
+    pub differs: bool,
+    /// Elements missing or extra compared to the baseline for each column
+    /// which disagrees with it, when either is a set
+    pub changes: Vec<Option<SetChange>>,
+}
+
+/// Difference between a set annotation and the baseline
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct SetChange {
+    /// Elements in the baseline but not this annotation
+    pub missing: Vec<String>,
+    /// Elements in this annotation but not the baseline
+    pub extra: Vec<String>,
+}
+
+impl SetChange {
+    /// Compares an annotation with the baseline as sets, returning `None` if
+    /// they have the same elements
+    fn between(baseline: &Annotation, annotation: &Annotation) -> Option<SetChange> {
+        let baseline_tokens: BTreeSet<&str> = baseline.tokens().collect();
+        let tokens: BTreeSet<&str> = annotation.tokens().collect();
+        if baseline_tokens == tokens {
+            return None;
+        }
+        Some(SetChange {
+            missing: baseline
+                .tokens()
+                .filter(|token| !tokens.contains(token))
+                .map(String::from)
+                .collect(),
+            extra: annotation
+                .tokens()
+                .filter(|token| !baseline_tokens.contains(token))
+                .map(String::from)
+                .collect(),
+        })
+    }
+}
+
+/// Written as `-missing +extra`
+impl fmt::Display for SetChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parts: Vec<String> = self
+            .missing
+            .iter()
+            .map(|element| format!("-{}", element))
+            .chain(self.extra.iter().map(|element| format!("+{}", element)))
+            .collect();
+        write!(f, "{}", parts.join(" "))
+    }
+}
+
+impl<'a> Table<'a> {
+    /// Combines each producer's annotations with the lines they apply to
+    pub fn new(
+        target: &'a str,
+        target_content: &'a str,
+        producers: &'a [Producer],
+        produced_annotations: &'a [Annotations],
+        diff: DiffOptions,
+    ) -> Table<'a> {
+        let columns: Vec<Column> = producers
+            .iter()
+            .zip(produced_annotations)
+            .enumerate()
+            .map(|(i, (producer, annotations))| Column {
+                producer,
+                key: column_key(i, producers),
+                max_width: annotations.max_width,
+                capabilities: &annotations.capabilities,
+            })
+            .collect();
+
+        let compared_columns = compared_columns(&diff, &columns);
+        let mut rows = Vec::new();
+        for (line_index, text) in target_content.lines().enumerate() {
+            let annotations: Vec<&Annotation> = produced_annotations
+                .iter()
+                .map(|a| &a.lines[line_index])
+                .collect();
+            let mut differing = vec![false; annotations.len()];
+            let mut changes = vec![None; annotations.len()];
+            if let Some(baseline) = annotations.get(diff.baseline) {
+                for &i in &compared_columns {
+                    let annotation = annotations[i];
+                    let kinds = [diff.baseline, i].map(|column| columns[column].capabilities.kind);
+                    if kinds == [Some(ValueKind::Boolean); 2] {
+                        // Marks are compared by whether they are present
+                        differing[i] =
+                            annotation.value.trim().is_empty() != baseline.value.trim().is_empty();
+                    } else if baseline.elements.is_some() || annotation.elements.is_some() {
+                        // Sets are compared regardless of order
+                        changes[i] = SetChange::between(baseline, annotation);
+                        differing[i] = changes[i].is_some();
+                    } else {
+                        // Blank annotations may be written as whitespace by
+                        // some producers
+                        differing[i] = annotation.value.trim() != baseline.value.trim();
+                    }
+                }
+            }
+            let differs = differing.contains(&true);
+
+            rows.push(Row {
+                number: line_index + 1,
+                text,
+                annotations,
+                differing,
+                differs,
+                changes,
+            });
+        }
+
+        // Skip lines far from any differences in diff only mode
+        if diff.only {
+            let differing_lines: Vec<usize> = rows
+                .iter()
+                .filter(|row| row.differs)
+                .map(|row| row.number)
+                .collect();
+            rows.retain(|row| {
+                differing_lines
+                    .iter()
+                    .any(|&line| row.number.abs_diff(line) <= diff.context)
+            });
+        }
+
+        let mut table = Table {
+            target,
+            line_count: target_content.lines().count(),
+            columns,
+            rows,
+            diff,
+            compared_columns,
+        };
+
+        // Make room for set changes shown in place of values
+        if table.highlighting_diff() {
+            for (i, annotations) in produced_annotations.iter().enumerate() {
+                let change_width = table
+                    .rows
+                    .iter()
+                    .filter_map(|row| row.changes[i].as_ref())
+                    .map(|change| width::display_width(&change.to_string()))
+                    .max()
+                    .unwrap_or_default();
+                let column = &mut table.columns[i];
+                column.max_width = column
+                    .max_width
+                    .max(change_width.min(annotations.width_cap));
+            }
+        }
+        table
+    }
+
+    /// Whether differences between producers should be highlighted
+    pub fn highlighting_diff(&self) -> bool {
+        self.diff.highlight && !self.compared_columns.is_empty()
+    }
+
+    /// Columns compared against the baseline, leaving out those that cannot
+    /// be diffed unless they were chosen explicitly
+    pub fn compared_columns(&self) -> &[usize] {
+        &self.compared_columns
+    }
+
+    /// Text to display for `column` (0-based) in `row`, which is the
+    /// elements missing and extra compared to the baseline when highlighting
+    /// differences between sets
+    pub fn cell_text<'r>(&self, row: &'r Row, column: usize) -> Cow<'r, str> {
+        match &row.changes[column] {
+            Some(change) if self.highlighting_diff() => Cow::Owned(change.to_string()),
+            _ => Cow::Borrowed(&row.annotations[column].value),
+        }
+    }
+
+    /// How to highlight the cell for `column` (0-based) in `row`, if at all
+    pub fn diff_highlight(&self, row: &Row, column: usize) -> Option<DiffHighlight> {
+        if !self.highlighting_diff() || !row.differs {
+            return None;
+        }
+        if column == self.diff.baseline {
+            return Some(DiffHighlight::Before);
+        }
+        if row.differing[column] {
+            return Some(DiffHighlight::After);
+        }
+        None
+    }
+
+    /// Lines (1-based) left out just before the row at `index`, or at the end
+    /// of the file when `index` is the number of rows
+    pub fn skipped_before(&self, index: usize) -> Option<RangeInclusive<usize>> {
+        let first = match index.checked_sub(1) {
+            Some(previous) => self.rows[previous].number + 1,
+            None => 1,
+        };
+        let last = match self.rows.get(index) {
+            Some(row) => row.number - 1,
+            None => self.line_count,
+        };
+        (first <= last).then_some(first..=last)
+    }
+
+    /// Metadata describing the table, used as the header of machine-readable
+    /// output
+    pub fn header(&self) -> TableHeader<'_> {
+        TableHeader {
+            target: self.target,
+            line_count: self.line_count,
+            producers: self
+                .columns
+                .iter()
+                .map(|column| ProducerHeader {
+                    key: &column.key,
+                    name: column.producer.name(),
+                    uri: column.producer.uri(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Columns compared against the baseline, as given by
+/// [`Table::compared_columns`]
+fn compared_columns(diff: &DiffOptions, columns: &[Column]) -> Vec<usize> {
+    diff.compared_columns(columns.len())
+        .into_iter()
+        .filter(|&i| diff.columns.is_some() || columns[i].capabilities.diffable)
+        .collect()
+}
+
+/// Picks a unique key for the producer's column, falling back to the full
+/// producer URI (and then position) when names are repeated
+fn column_key(index: usize, producers: &[Producer]) -> String {
+    let producer = &producers[index];
+    let same_name = producers
+        .iter()
+        .filter(|p| p.name() == producer.name())
+        .count();
+    if same_name == 1 {
+        return producer.name().to_string();
+    }
+    let same_uri: Vec<usize> = (0..producers.len())
+        .filter(|&i| producers[i].uri() == producer.uri())
+        .collect();
+    if same_uri.len() == 1 {
+        return producer.uri().to_string();
+    }
+    let position = same_uri.iter().position(|&i| i == index).unwrap();
+    format!("{}#{}", producer.uri(), position + 1)
+}
+
+/// Metadata describing the table for machine-readable output
+#[derive(Debug, Serialize)]
+pub struct TableHeader<'a> {
+    pub target: &'a str,
+    pub line_count: usize,
+    pub producers: Vec<ProducerHeader<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProducerHeader<'a> {
+    pub key: &'a str,
+    pub name: &'a str,
+    pub uri: &'a str,
+}
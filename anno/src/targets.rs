@@ -0,0 +1,124 @@
+use std::{
+    ffi::OsStr,
+    fs,
+    io::Read,
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context, Result};
+use globset::{GlobBuilder, GlobMatcher};
+use log::debug;
+use walkdir::WalkDir;
+
+/// Number of bytes read from the start of a file to check whether it is text
+const SNIFF_LENGTH: usize = 8 * 1024;
+
+/// Expands the files, directories, and glob patterns given by the user into
+/// the files to annotate
+///
+/// Directories are searched recursively. Files found in directories or by
+/// patterns are sorted by path, leaving out hidden files (and those in hidden
+/// directories) and files that are not UTF-8 text. Files given directly are
+/// always kept, so that any problem reading them is reported.
+pub fn expand_targets(inputs: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let mut targets: Vec<PathBuf> = Vec::new();
+    for input in inputs {
+        let expanded = if input.is_dir() {
+            find_files(input, None)?
+        } else if is_pattern(input) && !input.exists() {
+            let (base, pattern) = split_pattern(input);
+            let matcher = GlobBuilder::new(&pattern)
+                .literal_separator(true)
+                .build()
+                .with_context(|| format!("Invalid pattern `{}`", input.display()))?
+                .compile_matcher();
+            let found = find_files(&base, Some(&matcher))?;
+            if found.is_empty() {
+                return Err(anyhow!("No files match `{}`", input.display()));
+            }
+            found
+        } else {
+            vec![input.clone()]
+        };
+        for target in expanded {
+            if !targets.contains(&target) {
+                targets.push(target);
+            }
+        }
+    }
+    Ok(targets)
+}
+
+/// Whether `input` contains glob wildcards (`*`, `**`, or `?`)
+pub fn is_pattern(input: &Path) -> bool {
+    input.to_string_lossy().contains(['*', '?'])
+}
+
+/// Splits a pattern into the directory to search and the pattern to match
+/// against paths relative to that directory
+fn split_pattern(pattern: &Path) -> (PathBuf, String) {
+    let mut base = PathBuf::new();
+    let mut components = Vec::new();
+    for component in pattern.components() {
+        let text = component.as_os_str().to_string_lossy();
+        let wildcard = matches!(component, Component::Normal(_)) && text.contains(['*', '?']);
+        if components.is_empty() && !wildcard {
+            base.push(component);
+        } else {
+            components.push(text.into_owned());
+        }
+    }
+    (base, components.join("/"))
+}
+
+/// Finds text files under `dir` whose relative path matches `pattern`, or
+/// all of them if there is no pattern
+fn find_files(dir: &Path, pattern: Option<&GlobMatcher>) -> Result<Vec<PathBuf>> {
+    let root = if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    };
+    let mut files = Vec::new();
+    let walker = WalkDir::new(root)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|entry| entry.depth() == 0 || !is_hidden(entry.file_name()));
+    for entry in walker {
+        let entry =
+            entry.with_context(|| format!("Unable to search directory ({})", root.display()))?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let relative = entry.path().strip_prefix(root).unwrap();
+        if pattern.is_some_and(|pattern| !pattern.is_match(relative)) {
+            continue;
+        }
+        if !is_text(entry.path()) {
+            debug!("Skipping file that is not text: {}", entry.path().display());
+            continue;
+        }
+        files.push(dir.join(relative));
+    }
+    Ok(files)
+}
+
+fn is_hidden(name: &OsStr) -> bool {
+    name.to_string_lossy().starts_with('.')
+}
+
+/// Whether the start of the file at `path` is UTF-8 text, reading only enough
+/// to tell rather than the whole file
+fn is_text(path: &Path) -> bool {
+    let mut start = Vec::with_capacity(SNIFF_LENGTH);
+    let read = fs::File::open(path)
+        .and_then(|file| file.take(SNIFF_LENGTH as u64).read_to_end(&mut start));
+    if read.is_err() {
+        return false;
+    }
+    match std::str::from_utf8(&start) {
+        Ok(_) => true,
+        // A multi-byte character may be cut off at the end of what was read
+        Err(error) => error.error_len().is_none() && start.len() == SNIFF_LENGTH,
+    }
+}
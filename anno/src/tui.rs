@@ -0,0 +1,575 @@
+use std::{io, path::Path};
+
+use anyhow::{anyhow, Result};
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Paragraph},
+    DefaultTerminal, Frame,
+};
+
+use crate::{
+    annotations::Severity,
+    describe::Align,
+    table::{DiffHighlight, DiffOptions, Row, Table},
+    width, Annotated, Session,
+};
+
+const HELP: &str =
+    "q quit  ↑↓ scroll  / search  n/N match  ]/[ diff  1-9 column  enter detail  r re-run";
+
+/// Interactively browses the annotations for `target` in the terminal until
+/// the user quits
+///
+/// Producers are run once up front, and again whenever the user asks.
+pub fn run(session: &Session, target: &Path, diff: &DiffOptions) -> Result<()> {
+    if !io::IsTerminal::is_terminal(&io::stdout()) {
+        return Err(anyhow!(
+            "`--tui` can only be used when writing to a terminal"
+        ));
+    }
+    let annotated = session.annotate(target)?;
+    let mut view = View::new(annotated.producers.len());
+    let mut terminal = ratatui::try_init()?;
+    let result = browse(&mut terminal, session, target, diff, annotated, &mut view);
+    ratatui::restore();
+    result
+}
+
+/// What the user asked for after browsing a table
+enum Action {
+    Quit,
+    Rerun,
+}
+
+/// State of the interface which survives re-running producers
+struct View {
+    /// Index of the selected row
+    selected: usize,
+    /// Index of the first row on screen
+    offset: usize,
+    /// Height of the area rows were last drawn in
+    page: usize,
+    /// Whether each column is hidden
+    hidden: Vec<bool>,
+    /// Whether the detail pane for the selected row is shown
+    detail: bool,
+    /// Last search, matched against line text and annotations
+    search: String,
+    /// Search being typed, if any
+    input: Option<String>,
+    /// Message shown in the status bar until the next key press
+    message: Option<String>,
+}
+
+fn browse<'s>(
+    terminal: &mut DefaultTerminal,
+    session: &'s Session,
+    target: &Path,
+    diff: &DiffOptions,
+    mut annotated: Annotated<'s>,
+    view: &mut View,
+) -> Result<()> {
+    loop {
+        let table = annotated.table(diff.clone());
+        view.selected = view.selected.min(table.rows.len().saturating_sub(1));
+        match browse_table(terminal, &table, view)? {
+            Action::Quit => return Ok(()),
+            Action::Rerun => {
+                view.message = Some("Re-running producers...".to_string());
+                terminal.draw(|frame| draw(frame, &table, view))?;
+                view.message = match session.annotate(target) {
+                    Ok(rerun) => {
+                        annotated = rerun;
+                        Some("Producers re-run".to_string())
+                    }
+                    Err(error) => Some(format!("Unable to re-run producers: {:#}", error)),
+                };
+                // Producers may have written to the terminal
+                terminal.clear()?;
+            }
+        }
+    }
+}
+
+/// Draws `table` and handles key presses until the user quits or asks to
+/// re-run producers
+fn browse_table(terminal: &mut DefaultTerminal, table: &Table, view: &mut View) -> Result<Action> {
+    loop {
+        terminal.draw(|frame| draw(frame, table, view))?;
+        let key = match event::read()? {
+            Event::Key(key) if key.kind == KeyEventKind::Press => key,
+            _ => continue,
+        };
+        view.message = None;
+        if let Some(action) = view.handle_key(key, table) {
+            return Ok(action);
+        }
+    }
+}
+
+impl View {
+    fn new(column_count: usize) -> View {
+        View {
+            selected: 0,
+            offset: 0,
+            page: 1,
+            hidden: vec![false; column_count],
+            detail: false,
+            search: String::new(),
+            input: None,
+            message: None,
+        }
+    }
+
+    /// Updates the view for a key press, returning an action when the user
+    /// wants to leave the current table
+    fn handle_key(&mut self, key: KeyEvent, table: &Table) -> Option<Action> {
+        if let Some(input) = &mut self.input {
+            match key.code {
+                KeyCode::Enter => {
+                    self.search = self.input.take().unwrap_or_default();
+                    self.find(table, true);
+                }
+                KeyCode::Esc => self.input = None,
+                KeyCode::Backspace => {
+                    input.pop();
+                }
+                KeyCode::Char(c) => input.push(c),
+                _ => {}
+            }
+            return None;
+        }
+
+        let last = table.rows.len().saturating_sub(1);
+        match key.code {
+            KeyCode::Char('q') => return Some(Action::Quit),
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                return Some(Action::Quit)
+            }
+            KeyCode::Esc if self.detail => self.detail = false,
+            KeyCode::Esc => return Some(Action::Quit),
+            KeyCode::Char('r') => return Some(Action::Rerun),
+            KeyCode::Down | KeyCode::Char('j') => self.selected = (self.selected + 1).min(last),
+            KeyCode::Up | KeyCode::Char('k') => self.selected = self.selected.saturating_sub(1),
+            KeyCode::PageDown | KeyCode::Char(' ') => {
+                self.selected = (self.selected + self.page).min(last)
+            }
+            KeyCode::PageUp => self.selected = self.selected.saturating_sub(self.page),
+            KeyCode::Home | KeyCode::Char('g') => self.selected = 0,
+            KeyCode::End | KeyCode::Char('G') => self.selected = last,
+            KeyCode::Enter => self.detail = !self.detail,
+            KeyCode::Char('/') => self.input = Some(String::new()),
+            KeyCode::Char('n') => self.find(table, true),
+            KeyCode::Char('N') => self.find(table, false),
+            KeyCode::Char(']') => self.jump_to_diff(table, true),
+            KeyCode::Char('[') => self.jump_to_diff(table, false),
+            KeyCode::Char(c @ '1'..='9') => {
+                let column = c as usize - '1' as usize;
+                match self.hidden.get_mut(column) {
+                    Some(hidden) => *hidden = !*hidden,
+                    None => self.message = Some(format!("There is no column {}", c)),
+                }
+            }
+            _ => {}
+        }
+        None
+    }
+
+    /// Selects the next (or previous) row after the selected one for which
+    /// `matches` is true, wrapping around at the end of the table
+    fn select_next(
+        &mut self,
+        table: &Table,
+        forward: bool,
+        matches: impl Fn(&Row) -> bool,
+    ) -> bool {
+        let count = table.rows.len();
+        if count == 0 {
+            return false;
+        }
+        let found = (1..=count)
+            .map(|step| {
+                if forward {
+                    (self.selected + step) % count
+                } else {
+                    (self.selected + count - step % count) % count
+                }
+            })
+            .find(|&index| matches(&table.rows[index]));
+        if let Some(index) = found {
+            self.selected = index;
+        }
+        found.is_some()
+    }
+
+    fn find(&mut self, table: &Table, forward: bool) {
+        if self.search.is_empty() {
+            return;
+        }
+        let search = self.search.to_lowercase();
+        let found = self.select_next(table, forward, |row| {
+            row.text.to_lowercase().contains(&search)
+                || row
+                    .annotations
+                    .iter()
+                    .any(|annotation| annotation.value.to_lowercase().contains(&search))
+        });
+        if !found {
+            self.message = Some(format!("No matches for `{}`", self.search));
+        }
+    }
+
+    fn jump_to_diff(&mut self, table: &Table, forward: bool) {
+        if !self.select_next(table, forward, |row| row.differs) {
+            self.message = Some("No lines differ from the baseline".to_string());
+        }
+    }
+}
+
+fn draw(frame: &mut Frame, table: &Table, view: &mut View) {
+    let detail_height = if view.detail {
+        table.columns.len() as u16 + 2
+    } else {
+        0
+    };
+    let [header_area, rows_area, detail_area, status_area] = Layout::vertical([
+        Constraint::Length(1),
+        Constraint::Min(1),
+        Constraint::Length(detail_height),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+
+    // Keep the selected row on screen
+    view.page = (rows_area.height as usize).max(1);
+    if view.selected < view.offset {
+        view.offset = view.selected;
+    } else if view.selected >= view.offset + view.page {
+        view.offset = view.selected + 1 - view.page;
+    }
+
+    let number_width = table.line_count.to_string().len();
+    let dim = Style::new().fg(Color::DarkGray);
+
+    // Producer names stay fixed above the scrolling rows
+    let mut header = vec![Span::styled(format!("{:number_width$} │ ", ""), dim)];
+    for (i, column) in table.columns.iter().enumerate() {
+        if view.hidden[i] {
+            continue;
+        }
+        header.push(Span::styled(
+            column.fit_header(),
+            Style::new().add_modifier(Modifier::BOLD),
+        ));
+        header.push(Span::styled(" │ ", dim));
+    }
+    frame.render_widget(Paragraph::new(Line::from(header)), header_area);
+
+    let rows: Vec<Line> = table
+        .rows
+        .iter()
+        .enumerate()
+        .skip(view.offset)
+        .take(view.page)
+        .map(|(index, row)| {
+            let mut spans = vec![Span::styled(
+                format!("{:>number_width$} │ ", row.number),
+                dim,
+            )];
+            for (i, column) in table.columns.iter().enumerate() {
+                if view.hidden[i] {
+                    continue;
+                }
+                spans.push(Span::styled(
+                    column.fit(&table.cell_text(row, i)),
+                    cell_style(table, row, i),
+                ));
+                spans.push(Span::styled(" │ ", dim));
+            }
+            spans.push(Span::raw(row.text.replace('\t', "    ")));
+            let line = Line::from(spans);
+            if index == view.selected {
+                line.style(Style::new().add_modifier(Modifier::REVERSED))
+            } else {
+                line
+            }
+        })
+        .collect();
+    frame.render_widget(Paragraph::new(rows), rows_area);
+
+    if view.detail {
+        draw_detail(frame, table, view, detail_area);
+    }
+
+    let status = match (&view.input, &view.message) {
+        (Some(input), _) => format!("/{}", input),
+        (None, Some(message)) => message.clone(),
+        (None, None) => {
+            let line = table.rows.get(view.selected).map_or(0, |row| row.number);
+            format!("{} {}/{}  {}", table.target, line, table.line_count, HELP)
+        }
+    };
+    frame.render_widget(
+        Paragraph::new(status).style(Style::new().add_modifier(Modifier::REVERSED)),
+        status_area,
+    );
+}
+
+/// Shows every annotation for the selected row in full
+fn draw_detail(frame: &mut Frame, table: &Table, view: &View, area: Rect) {
+    let Some(row) = table.rows.get(view.selected) else {
+        return;
+    };
+    let key_width = table
+        .columns
+        .iter()
+        .map(|column| width::display_width(&column.key))
+        .max()
+        .unwrap_or_default();
+    let lines: Vec<Line> = table
+        .columns
+        .iter()
+        .zip(&row.annotations)
+        .enumerate()
+        .map(|(i, (column, annotation))| {
+            let mut spans = vec![
+                Span::styled(
+                    format!("{}  ", width::pad(&column.key, key_width, Align::Left)),
+                    Style::new().add_modifier(Modifier::BOLD),
+                ),
+                Span::styled(annotation.value.clone(), cell_style(table, row, i)),
+            ];
+            if let Some(change) = &row.changes[i] {
+                spans.push(Span::raw(format!("  ({})", change)));
+            }
+            if let Some(detail) = &annotation.detail {
+                spans.push(Span::styled(
+                    format!("  {}", detail),
+                    Style::new().fg(Color::DarkGray),
+                ));
+            }
+            Line::from(spans)
+        })
+        .collect();
+    let block = Block::bordered().title(format!(" Line {} ", row.number));
+    frame.render_widget(Paragraph::new(lines).block(block), area);
+}
+
+/// Colours annotations by severity and highlights differences in diff mode,
+/// matching terminal output
+fn cell_style(table: &Table, row: &Row, column: usize) -> Style {
+    match table.diff_highlight(row, column) {
+        Some(DiffHighlight::Before) => Style::new().fg(Color::Red).add_modifier(Modifier::BOLD),
+        Some(DiffHighlight::After) => Style::new().fg(Color::Green).add_modifier(Modifier::BOLD),
+        None => match row.annotations[column].severity {
+            Some(Severity::Warning) => Style::new().fg(Color::Yellow),
+            Some(Severity::Error) => Style::new().fg(Color::Red),
+            Some(Severity::Info) | None => Style::new(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Annotation, Annotations, Producer};
+
+    const CONTENT: &str = "int a;\nint b;\nint c;\nint d;\n";
+
+    fn annotations(values: &[&str]) -> Annotations {
+        Annotations::new(
+            values
+                .iter()
+                .map(|value| Annotation {
+                    value: value.to_string(),
+                    ..Default::default()
+                })
+                .collect(),
+        )
+    }
+
+    /// Runs `test` with a table of two producers, which differ on lines 2
+    /// and 3
+    fn with_table(test: impl FnOnce(&Table)) {
+        let producers = [
+            Producer::parse("first:").unwrap(),
+            Producer::parse("second:").unwrap(),
+        ];
+        let annotations = [
+            annotations(&["x", "", "x", ""]),
+            annotations(&["x", "x", "", ""]),
+        ];
+        let diff = DiffOptions {
+            highlight: true,
+            ..Default::default()
+        };
+        test(&Table::new(
+            "example.c",
+            CONTENT,
+            &producers,
+            &annotations,
+            diff,
+        ));
+    }
+
+    fn press(view: &mut View, table: &Table, code: KeyCode) -> Option<Action> {
+        view.handle_key(KeyEvent::new(code, KeyModifiers::NONE), table)
+    }
+
+    #[test]
+    fn moves_within_table() {
+        with_table(|table| {
+            let mut view = View::new(2);
+            view.page = 2;
+            for _ in 0..5 {
+                press(&mut view, table, KeyCode::Down);
+            }
+            assert_eq!(view.selected, 3);
+            press(&mut view, table, KeyCode::Up);
+            assert_eq!(view.selected, 2);
+            press(&mut view, table, KeyCode::Home);
+            assert_eq!(view.selected, 0);
+            press(&mut view, table, KeyCode::PageDown);
+            assert_eq!(view.selected, 2);
+            press(&mut view, table, KeyCode::PageDown);
+            assert_eq!(view.selected, 3);
+            press(&mut view, table, KeyCode::PageUp);
+            assert_eq!(view.selected, 1);
+            press(&mut view, table, KeyCode::End);
+            assert_eq!(view.selected, 3);
+        });
+    }
+
+    #[test]
+    fn jumps_between_differences() {
+        with_table(|table| {
+            let mut view = View::new(2);
+            view.selected = 3;
+            // Wraps around the end of the table
+            press(&mut view, table, KeyCode::Char(']'));
+            assert_eq!(view.selected, 1);
+            press(&mut view, table, KeyCode::Char(']'));
+            assert_eq!(view.selected, 2);
+            // Wraps around the start going backward
+            view.selected = 0;
+            press(&mut view, table, KeyCode::Char('['));
+            assert_eq!(view.selected, 2);
+            assert_eq!(view.message, None);
+        });
+    }
+
+    #[test]
+    fn select_next_reaches_selected_row_last() {
+        with_table(|table| {
+            let mut view = View::new(2);
+            view.selected = 1;
+            // Only the selected row matches, so both directions come back to
+            // it after a full lap
+            assert!(view.select_next(table, true, |row| row.number == 2));
+            assert_eq!(view.selected, 1);
+            assert!(view.select_next(table, false, |row| row.number == 2));
+            assert_eq!(view.selected, 1);
+            assert!(!view.select_next(table, false, |_| false));
+            assert_eq!(view.selected, 1);
+        });
+    }
+
+    #[test]
+    fn searches_text_and_annotations() {
+        with_table(|table| {
+            let mut view = View::new(2);
+            press(&mut view, table, KeyCode::Char('/'));
+            for c in "C;".chars() {
+                press(&mut view, table, KeyCode::Char(c));
+            }
+            assert_eq!(view.input.as_deref(), Some("C;"));
+            press(&mut view, table, KeyCode::Enter);
+            assert_eq!(view.input, None);
+            assert_eq!(view.selected, 2);
+
+            // Annotation values match too
+            view.search = "x".to_string();
+            press(&mut view, table, KeyCode::Char('n'));
+            assert_eq!(view.selected, 0);
+            press(&mut view, table, KeyCode::Char('N'));
+            assert_eq!(view.selected, 2);
+
+            view.search = "missing".to_string();
+            press(&mut view, table, KeyCode::Char('n'));
+            assert_eq!(view.selected, 2);
+            assert_eq!(view.message.as_deref(), Some("No matches for `missing`"));
+        });
+    }
+
+    #[test]
+    fn search_input_cancelled() {
+        with_table(|table| {
+            let mut view = View::new(2);
+            press(&mut view, table, KeyCode::Char('/'));
+            press(&mut view, table, KeyCode::Char('d'));
+            press(&mut view, table, KeyCode::Backspace);
+            assert_eq!(view.input.as_deref(), Some(""));
+            // Keys are typed into the search rather than acted on
+            assert!(press(&mut view, table, KeyCode::Char('q')).is_none());
+            press(&mut view, table, KeyCode::Esc);
+            assert_eq!(view.input, None);
+            assert!(view.search.is_empty());
+        });
+    }
+
+    #[test]
+    fn toggles_columns() {
+        with_table(|table| {
+            let mut view = View::new(2);
+            press(&mut view, table, KeyCode::Char('2'));
+            assert_eq!(view.hidden, [false, true]);
+            press(&mut view, table, KeyCode::Char('3'));
+            assert_eq!(view.hidden, [false, true]);
+            assert_eq!(view.message.as_deref(), Some("There is no column 3"));
+            press(&mut view, table, KeyCode::Char('2'));
+            assert_eq!(view.hidden, [false, false]);
+        });
+    }
+
+    #[test]
+    fn leaves_detail_before_quitting() {
+        with_table(|table| {
+            let mut view = View::new(2);
+            press(&mut view, table, KeyCode::Enter);
+            assert!(view.detail);
+            assert!(press(&mut view, table, KeyCode::Esc).is_none());
+            assert!(!view.detail);
+            assert!(matches!(
+                press(&mut view, table, KeyCode::Esc),
+                Some(Action::Quit)
+            ));
+            assert!(matches!(
+                press(&mut view, table, KeyCode::Char('r')),
+                Some(Action::Rerun)
+            ));
+        });
+    }
+
+    #[test]
+    fn no_differences_reported() {
+        let producers = [Producer::parse("only:").unwrap()];
+        let annotations = [annotations(&["x", "", "", ""])];
+        let table = Table::new(
+            "example.c",
+            CONTENT,
+            &producers,
+            &annotations,
+            DiffOptions::default(),
+        );
+        let mut view = View::new(1);
+        press(&mut view, &table, KeyCode::Char(']'));
+        assert_eq!(view.selected, 0);
+        assert_eq!(
+            view.message.as_deref(),
+            Some("No lines differ from the baseline")
+        );
+    }
+}
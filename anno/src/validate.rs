@@ -0,0 +1,173 @@
+use std::fmt;
+
+use clap::ValueEnum;
+
+/// What to do when a producer's output does not cover the target exactly
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum MismatchPolicy {
+    /// Stop with an error describing the mismatch
+    #[default]
+    Error,
+    /// Fill in blank annotations for lines the producer skipped
+    Pad,
+    /// Drop annotations beyond the end of the target
+    Truncate,
+}
+
+/// The first line where producer output and target disagree
+#[derive(Debug, PartialEq, Eq)]
+pub enum OffendingLine {
+    /// Producer output continues past the end of the target
+    Extra { line: usize, content: String },
+    /// Producer output ends before the target does
+    Missing { line: usize },
+}
+
+/// Producer output with a different number of lines than the target
+#[derive(Debug, PartialEq, Eq)]
+pub struct LineCountMismatch {
+    /// Name of the producer that supplied the output
+    pub producer: String,
+    /// Number of lines in the target
+    pub expected: usize,
+    /// Number of lines in the producer's output
+    pub actual: usize,
+    /// First line where the two disagree
+    pub first_offending: OffendingLine,
+}
+
+impl fmt::Display for LineCountMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Annotation producer `{}` produced {} lines of output, but the file being annotated has {} lines",
+            self.producer, self.actual, self.expected
+        )?;
+        match &self.first_offending {
+            OffendingLine::Extra { line, content } => {
+                writeln!(f, "  first unexpected line: {}: {:?}", line, content)?;
+                write!(f, "  (use `--on-mismatch=truncate` to drop extra lines)")
+            }
+            OffendingLine::Missing { line } => {
+                writeln!(f, "  first line without an annotation: {}", line)?;
+                write!(
+                    f,
+                    "  (use `--on-mismatch=pad` to fill in blank annotations)"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for LineCountMismatch {}
+
+/// Checks producer output has exactly one line per target line, adjusting
+/// it according to `policy` when it does not
+pub(crate) fn validate_line_count(
+    producer: &str,
+    data: String,
+    expected: usize,
+    policy: MismatchPolicy,
+) -> Result<String, LineCountMismatch> {
+    let actual = data.lines().count();
+    if actual == expected {
+        return Ok(data);
+    }
+
+    let mismatch = if actual > expected {
+        LineCountMismatch {
+            producer: producer.to_string(),
+            expected,
+            actual,
+            first_offending: OffendingLine::Extra {
+                line: expected + 1,
+                content: data.lines().nth(expected).unwrap().to_string(),
+            },
+        }
+    } else {
+        LineCountMismatch {
+            producer: producer.to_string(),
+            expected,
+            actual,
+            first_offending: OffendingLine::Missing { line: actual + 1 },
+        }
+    };
+
+    match (policy, &mismatch.first_offending) {
+        (MismatchPolicy::Pad, OffendingLine::Missing { .. }) => {
+            eprintln!(
+                "Warning: Padding output from `{}` with {} blank annotations",
+                producer,
+                expected - actual
+            );
+            let mut padded = data;
+            if !padded.is_empty() && !padded.ends_with('\n') {
+                padded.push('\n');
+            }
+            padded.push_str(&"\n".repeat(expected - actual));
+            Ok(padded)
+        }
+        (MismatchPolicy::Truncate, OffendingLine::Extra { .. }) => {
+            eprintln!(
+                "Warning: Dropping {} extra lines of output from `{}`",
+                actual - expected,
+                producer
+            );
+            let truncated: Vec<&str> = data.lines().take(expected).collect();
+            Ok(truncated.join("\n"))
+        }
+        _ => Err(mismatch),
+    }
+}
+
+/// Producer record for a line outside the target
+#[derive(Debug, PartialEq, Eq)]
+pub struct LineOutOfRange {
+    /// Name of the producer that supplied the record
+    pub producer: String,
+    /// Line number given by the record
+    pub line: usize,
+    /// Number of lines in the target
+    pub line_count: usize,
+}
+
+impl fmt::Display for LineOutOfRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Annotation producer `{}` supplied an annotation for line {}, but the file being annotated has {} lines",
+            self.producer, self.line, self.line_count
+        )?;
+        write!(
+            f,
+            "  (use `--on-mismatch=truncate` to drop such annotations)"
+        )
+    }
+}
+
+impl std::error::Error for LineOutOfRange {}
+
+/// Checks a (1-based) line number from a producer record lies within the
+/// target, returning whether the record should be kept
+pub(crate) fn validate_record_line(
+    producer: &str,
+    line: usize,
+    line_count: usize,
+    policy: MismatchPolicy,
+) -> Result<bool, LineOutOfRange> {
+    if line >= 1 && line <= line_count {
+        return Ok(true);
+    }
+    if policy == MismatchPolicy::Truncate {
+        eprintln!(
+            "Warning: Dropping annotation for line {} from `{}`",
+            line, producer
+        );
+        return Ok(false);
+    }
+    Err(LineOutOfRange {
+        producer: producer.to_string(),
+        line,
+        line_count,
+    })
+}
@@ -0,0 +1,128 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver, RecvTimeoutError},
+    time::Duration,
+};
+
+use anyhow::{anyhow, Context, Result};
+use log::{debug, warn};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::{session::Annotated, Session};
+
+/// How long to wait for further changes before re-running producers, so that
+/// a rebuild writing several files only triggers one run
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// Annotates `target`, then re-runs producers whenever the target or their
+/// data sources change, passing each result to `show`
+///
+/// A change to the target re-runs every producer, while a change to a data
+/// source only re-runs the producers using it. Data sources are watched when
+/// they name an existing file or directory. Failures while re-running are
+/// reported without ending the watch, which runs until interrupted.
+pub fn watch(
+    session: &Session,
+    target: &Path,
+    mut show: impl FnMut(&Annotated) -> Result<()>,
+) -> Result<()> {
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender)?;
+    let target_path = target
+        .canonicalize()
+        .with_context(|| format!("Unable to watch file ({})", target.display()))?;
+    watch_path(&mut watcher, &target_path)?;
+    let sources: Vec<Option<PathBuf>> = session
+        .producers
+        .iter()
+        .map(|producer| source_path(producer.source()))
+        .collect();
+    for source in sources.iter().flatten() {
+        watch_path(&mut watcher, source)?;
+    }
+
+    let mut annotated = session.annotate(target)?;
+    show(&annotated)?;
+    loop {
+        let changed = wait_for_changes(&receiver)?;
+        debug!("Changed: {:?}", changed);
+        let result = if changed.contains(&target_path) {
+            session.annotate(target).map(|rerun| annotated = rerun)
+        } else {
+            let producers: Vec<usize> = sources
+                .iter()
+                .enumerate()
+                .filter(|(_, source)| {
+                    source
+                        .as_ref()
+                        .is_some_and(|source| changed.iter().any(|path| path.starts_with(source)))
+                })
+                .map(|(index, _)| index)
+                .collect();
+            if producers.is_empty() {
+                continue;
+            }
+            session.reannotate(&mut annotated, &producers)
+        };
+        match result {
+            Ok(()) => show(&annotated)?,
+            Err(error) => eprintln!("Error: {:#}", error),
+        }
+    }
+}
+
+/// Existing file or directory named by a producer's data source
+fn source_path(source: &str) -> Option<PathBuf> {
+    if source.is_empty() {
+        return None;
+    }
+    Path::new(source).canonicalize().ok()
+}
+
+/// Watches a directory and everything in it, or a file via its directory
+/// (so files replaced by renaming over them are still noticed)
+fn watch_path(watcher: &mut RecommendedWatcher, path: &Path) -> Result<()> {
+    let (watched, mode) = if path.is_dir() {
+        (path, RecursiveMode::Recursive)
+    } else {
+        let parent = path
+            .parent()
+            .ok_or_else(|| anyhow!("Unable to watch file ({})", path.display()))?;
+        (parent, RecursiveMode::NonRecursive)
+    };
+    debug!("Watching: {}", watched.display());
+    watcher
+        .watch(watched, mode)
+        .with_context(|| format!("Unable to watch for changes ({})", watched.display()))
+}
+
+/// Blocks until something changes and no further changes have been seen for
+/// a short while, returning the paths that changed
+fn wait_for_changes(receiver: &Receiver<notify::Result<Event>>) -> Result<Vec<PathBuf>> {
+    let mut changed: Vec<PathBuf> = Vec::new();
+    loop {
+        let event = if changed.is_empty() {
+            receiver.recv()?
+        } else {
+            match receiver.recv_timeout(DEBOUNCE) {
+                Ok(event) => event,
+                Err(RecvTimeoutError::Timeout) => return Ok(changed),
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(anyhow!("Stopped watching for changes"))
+                }
+            }
+        };
+        match event {
+            // Producers reading files must not trigger another run
+            Ok(event) if !matches!(event.kind, EventKind::Access(_)) => {
+                for path in event.paths {
+                    if !changed.contains(&path) {
+                        changed.push(path);
+                    }
+                }
+            }
+            Ok(_) => {}
+            Err(error) => warn!("Unable to watch for changes: {}", error),
+        }
+    }
+}
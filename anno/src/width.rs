@@ -0,0 +1,96 @@
+//! Measuring and fitting text by the number of terminal columns it takes up,
+//! so that wide characters (such as CJK) and combining marks keep columns
+//! aligned.
+
+use unicode_width::UnicodeWidthChar;
+
+use crate::describe::Align;
+
+/// Marks the end of text cut short to fit a column
+pub const ELLIPSIS: char = '…';
+
+/// Number of terminal columns taken up by `c`, treating control characters
+/// as taking none
+fn char_width(c: char) -> usize {
+    c.width().unwrap_or_default()
+}
+
+/// Number of terminal columns taken up by `text`
+pub fn display_width(text: &str) -> usize {
+    text.chars().map(char_width).sum()
+}
+
+/// Longest prefix of `text` taking up at most `width` columns
+pub fn cut(text: &str, width: usize) -> &str {
+    let mut used = 0;
+    for (i, c) in text.char_indices() {
+        used += char_width(c);
+        if used > width {
+            return &text[..i];
+        }
+    }
+    text
+}
+
+/// Cuts `text` to at most `width` columns, ending it with an ellipsis when
+/// anything is left out
+pub fn truncate(text: &str, width: usize) -> String {
+    if display_width(text) <= width {
+        return text.to_string();
+    }
+    if width == 0 {
+        return String::new();
+    }
+    let mut truncated = cut(text, width - 1).to_string();
+    truncated.push(ELLIPSIS);
+    truncated
+}
+
+/// Pads `text` with spaces to `width` columns, aligned as given
+///
+/// Text already wider than `width` is returned unchanged.
+pub fn pad(text: &str, width: usize, align: Align) -> String {
+    let padding = " ".repeat(width.saturating_sub(display_width(text)));
+    match align {
+        Align::Left => format!("{}{}", text, padding),
+        Align::Right => format!("{}{}", padding, text),
+    }
+}
+
+/// Splits `text` into lines of at most `width` columns, breaking between
+/// words where possible
+///
+/// Words wider than `width` are broken wherever they reach it. Empty text
+/// gives a single empty line.
+pub fn wrap(text: &str, width: usize) -> Vec<String> {
+    let width = width.max(1);
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        let line_width = display_width(&line);
+        let word_width = display_width(word);
+        if !line.is_empty() && line_width + 1 + word_width <= width {
+            line.push(' ');
+            line.push_str(word);
+            continue;
+        }
+        if !line.is_empty() {
+            lines.push(std::mem::take(&mut line));
+        }
+        let mut rest = word;
+        while display_width(rest) > width {
+            let mut piece = cut(rest, width);
+            if piece.is_empty() {
+                // A single character wider than the line still needs a line
+                piece = &rest[..rest.chars().next().unwrap().len_utf8()];
+            }
+            lines.push(piece.to_string());
+            rest = &rest[piece.len()..];
+        }
+        line.push_str(rest);
+    }
+    if !line.is_empty() || lines.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
@@ -0,0 +1,119 @@
+use std::{fs, os::unix::fs::PermissionsExt, path::Path, process::Command};
+
+use assert_cmd::prelude::*;
+use predicates::prelude::*;
+
+mod common;
+
+use common::anno;
+
+/// Annotates `target` with the `runs` producer (which reports how many times
+/// it has run) and the `cat` producer reading `source`
+fn annotate(dir: &Path, cache_home: &Path) -> Command {
+  let mut cmd = anno();
+  cmd.current_dir(dir);
+  cmd.env("XDG_CACHE_HOME", cache_home);
+  cmd.args(["target.c", "-p", "runs:?file=runs", "-p", "cat:source.jsonl", "--color", "never"]);
+  cmd
+}
+
+#[test]
+fn cache_reuses_output() -> Result<(), Box<dyn std::error::Error>> {
+  let dir = tempfile::tempdir()?;
+  let cache_home = tempfile::tempdir()?;
+  let cache_home = cache_home.path().join("cache");
+  fs::write(dir.path().join("target.c"), "a\n")?;
+  fs::write(dir.path().join("source.jsonl"), "{\"protocol\": \"jsonl\", \"version\": 1}\n")?;
+
+  annotate(dir.path(), &cache_home).assert().success().stdout("… |  | \n1 |  | a\n");
+  annotate(dir.path(), &cache_home)
+    .arg("-v")
+    .assert()
+    .success()
+    .stdout("… |  | \n1 |  | a\n")
+    .stderr(predicate::str::contains("Using cached output for `runs:?file=runs`"));
+  assert_eq!(fs::read_to_string(dir.path().join("runs"))?.lines().count(), 1);
+
+  // Changing the file or a data source runs producers again
+  fs::write(dir.path().join("target.c"), "b\n")?;
+  annotate(dir.path(), &cache_home).assert().success().stdout("… |  | \n2 |  | b\n");
+  fs::write(
+    dir.path().join("source.jsonl"),
+    "{\"protocol\": \"jsonl\", \"version\": 1}\n{\"line\": 1, \"value\": \"x\"}\n",
+  )?;
+  annotate(dir.path(), &cache_home).assert().success().stdout("… | … | \n2 | x | b\n");
+  Ok(())
+}
+
+#[test]
+fn cache_refresh_and_disable() -> Result<(), Box<dyn std::error::Error>> {
+  let dir = tempfile::tempdir()?;
+  let cache_home = tempfile::tempdir()?;
+  let cache_home = cache_home.path().join("cache");
+  fs::write(dir.path().join("target.c"), "a\n")?;
+  fs::write(dir.path().join("source.jsonl"), "{\"protocol\": \"jsonl\", \"version\": 1}\n")?;
+
+  // Without the cache, producers always run and nothing is stored
+  annotate(dir.path(), &cache_home).arg("--no-cache").assert().success().stdout("… |  | \n1 |  | a\n");
+  annotate(dir.path(), &cache_home).arg("--no-cache").assert().success().stdout("… |  | \n2 |  | a\n");
+  assert!(!cache_home.exists());
+
+  // Refreshing runs producers again and stores the new output
+  annotate(dir.path(), &cache_home).assert().success().stdout("… |  | \n3 |  | a\n");
+  annotate(dir.path(), &cache_home).arg("--refresh").assert().success().stdout("… |  | \n4 |  | a\n");
+  annotate(dir.path(), &cache_home).assert().success().stdout("… |  | \n4 |  | a\n");
+  Ok(())
+}
+
+#[test]
+fn cache_covers_inherited_env() -> Result<(), Box<dyn std::error::Error>> {
+  let dir = tempfile::tempdir()?;
+  let cache_home = tempfile::tempdir()?;
+  fs::write(dir.path().join("target.c"), "a\n")?;
+  // Says it only reads `FOO`, but reports `BAR`
+  let producer = dir.path().join("anno-bar");
+  fs::write(
+    &producer,
+    "#!/bin/sh\n[ -n \"$ANNO_DESCRIBE\" ] && echo '{\"describe\": 1, \"env\": [\"FOO\"]}' && exit\necho \"$BAR\"\n",
+  )?;
+  fs::set_permissions(&producer, fs::Permissions::from_mode(0o755))?;
+
+  let annotate = |foo: &str, bar: &str| {
+    let mut cmd = anno();
+    cmd.current_dir(dir.path());
+    cmd.env("XDG_CACHE_HOME", cache_home.path());
+    cmd.env("FOO", foo);
+    cmd.env("BAR", bar);
+    cmd.args(["target.c", "-p", "env:?var=FOO", "-p", "./anno-bar:", "--color", "never"]);
+    cmd
+  };
+  annotate("one", "one").assert().success().stdout("env | bar | \none | one | a\n");
+  // Producers which do not list the variables they read are run again when
+  // any change, and others only when those they list do
+  annotate("two", "one").assert().success().stdout("env | bar | \ntwo | one | a\n");
+  annotate("two", "two").assert().success().stdout("env | bar | \ntwo | one | a\n");
+  annotate("three", "two").assert().success().stdout("env   | bar | \nthree | two | a\n");
+  Ok(())
+}
+
+#[test]
+fn cache_keeps_failed_descriptions() -> Result<(), Box<dyn std::error::Error>> {
+  let dir = tempfile::tempdir()?;
+  let cache_home = tempfile::tempdir()?;
+  fs::write(dir.path().join("target.c"), "a\n")?;
+  // Records each run, which `ANNO_DESCRIBE` tells apart from annotating
+  let producer = dir.path().join("anno-calls");
+  fs::write(&producer, "#!/bin/sh\necho \"describe=$ANNO_DESCRIBE\" >> calls\necho x\n")?;
+  fs::set_permissions(&producer, fs::Permissions::from_mode(0o755))?;
+
+  for _ in 0..2 {
+    let mut cmd = anno();
+    cmd.current_dir(dir.path());
+    cmd.env("XDG_CACHE_HOME", cache_home.path());
+    cmd.args(["target.c", "-p", "./anno-calls:", "--color", "never"]);
+    cmd.assert().success().stdout("… | \nx | a\n");
+  }
+  // Neither the output nor the lack of a description is asked for again
+  assert_eq!(fs::read_to_string(dir.path().join("calls"))?, "describe=1\ndescribe=\n");
+  Ok(())
+}
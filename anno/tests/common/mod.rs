@@ -0,0 +1,39 @@
+// Not every test uses every helper
+#![allow(dead_code)]
+
+use std::{
+  env,
+  ffi::OsString,
+  iter,
+  path::PathBuf,
+  process::Command,
+};
+
+use assert_cmd::prelude::*;
+
+/// Directory containing test producers and files to annotate
+pub fn fixtures() -> PathBuf {
+  PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures")
+}
+
+/// `PATH` with test producers added
+pub fn test_path() -> OsString {
+  let system_path = env::var_os("PATH").unwrap_or_default();
+  let paths = iter::once(fixtures().join("bin")).chain(env::split_paths(&system_path));
+  env::join_paths(paths).unwrap()
+}
+
+/// Command for running `anno` from the fixtures directory with test producers
+/// available in `PATH` (and without any user configuration or cached output)
+///
+/// No cache location is known without `XDG_CACHE_HOME` or `HOME`, so output is
+/// never cached unless a test gives its own cache directory.
+pub fn anno() -> Command {
+  let mut cmd = Command::cargo_bin("anno").unwrap();
+  cmd.env("PATH", test_path());
+  cmd.env("XDG_CONFIG_HOME", fixtures().join("no-config-home"));
+  cmd.env_remove("XDG_CACHE_HOME");
+  cmd.env_remove("HOME");
+  cmd.current_dir(fixtures());
+  cmd
+}
@@ -0,0 +1,104 @@
+use std::fs;
+
+use assert_cmd::prelude::*;
+use predicates::prelude::*;
+
+mod common;
+
+use common::{anno, fixtures};
+
+#[test]
+fn preset_from_project_config() -> Result<(), Box<dyn std::error::Error>> {
+  // Config is found next to the target, with sources relative to it
+  let mut cmd = anno();
+  cmd.args(["project/example.c", "--preset", "review"]);
+  cmd
+    .assert()
+    .success()
+    .stdout(predicate::str::contains(r#""uri": "cat:"#))
+    .stdout(predicate::str::contains("project/sparse.jsonl"))
+    .stdout(predicate::str::contains(r#""value": "x z""#))
+    .stdout(predicate::str::contains(r#""value": "hello""#));
+  Ok(())
+}
+
+#[test]
+fn command_line_overrides_config() -> Result<(), Box<dyn std::error::Error>> {
+  let mut cmd = anno();
+  cmd.args(["project/example.c", "--preset", "review", "--format", "terminal"]);
+  cmd
+    .assert()
+    .success()
+    .stdout(predicate::str::contains("x z | he… | int square(int x) {\n"));
+
+  let mut cmd = anno();
+  cmd.args(["project/example.c", "--preset", "compare", "--max-width", "10"]);
+  cmd
+    .assert()
+    .success()
+    .stdout(predicate::str::contains("3  | preset | int square(int x) {\n"));
+  Ok(())
+}
+
+#[test]
+fn preset_from_user_config() -> Result<(), Box<dyn std::error::Error>> {
+  let mut cmd = anno();
+  cmd.env("XDG_CONFIG_HOME", fixtures().join("config-home"));
+  cmd.args(["example.c", "--preset", "numbered", "-p", "computable-expressions"]);
+  cmd
+    .assert()
+    .success()
+    .stdout(predicate::str::starts_with("n… | … | \n1  |   | #include <stdio.h>\n"));
+  Ok(())
+}
+
+#[test]
+fn preset_missing() -> Result<(), Box<dyn std::error::Error>> {
+  let mut cmd = anno();
+  cmd.args(["project/example.c", "--preset", "missing"]);
+  cmd.assert().failure().stderr(predicate::str::contains(
+    "No preset named `missing` (available: compare, review)",
+  ));
+  Ok(())
+}
+
+#[test]
+fn column_widths_from_config() -> Result<(), Box<dyn std::error::Error>> {
+  let dir = tempfile::tempdir()?;
+  fs::write(
+    dir.path().join("anno.toml"),
+    "[env]\nANNO_TEST_GREETING = \"hello\"\n\n\
+     [display]\nmax-width = 2\ncolumn-widths = { env = 4 }\n",
+  )?;
+  fs::write(dir.path().join("target.c"), "a\nb\n")?;
+
+  let mut cmd = anno();
+  cmd.current_dir(dir.path());
+  cmd.args(["target.c", "-p", "env:?var=ANNO_TEST_GREETING", "-p", "numbers:"]);
+  cmd.assert().success().stdout("env  | … | \nhel… | 1 | a\nhel… | 2 | b\n");
+
+  // Producers named on the command line override the config
+  let mut cmd = anno();
+  cmd.current_dir(dir.path());
+  cmd.args(["target.c", "-p", "env:?var=ANNO_TEST_GREETING", "--max-width", "env=5"]);
+  cmd.assert().success().stdout("env   | \nhello | a\nhello | b\n");
+  Ok(())
+}
+
+#[test]
+fn command_line_turns_off_config_diff() -> Result<(), Box<dyn std::error::Error>> {
+  let dir = tempfile::tempdir()?;
+  fs::write(dir.path().join("anno.toml"), "[display]\ndiff = true\ndiff-only = true\n")?;
+  fs::write(dir.path().join("target.c"), "a\nb\n")?;
+
+  let mut cmd = anno();
+  cmd.current_dir(dir.path());
+  cmd.args(["target.c", "-p", "numbers:", "-p", "numbers:", "--color", "never"]);
+  cmd.assert().success().stdout("  | … | … | \n... (lines 1-2 skipped)\n");
+
+  let mut cmd = anno();
+  cmd.current_dir(dir.path());
+  cmd.args(["target.c", "-p", "numbers:", "-p", "numbers:", "--no-diff-only", "--no-diff"]);
+  cmd.assert().success().stdout("… | … | \n1 | 1 | a\n2 | 2 | b\n");
+  Ok(())
+}
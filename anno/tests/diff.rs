@@ -0,0 +1,242 @@
+use assert_cmd::prelude::*;
+use predicates::prelude::*;
+
+mod common;
+
+use common::anno;
+
+#[test]
+fn diff_selected_columns() -> Result<(), Box<dyn std::error::Error>> {
+  let mut cmd = anno();
+  cmd.args([
+    "example.c",
+    "-p",
+    "computable-expressions",
+    "-p",
+    "dwarf-line-table",
+    "-p",
+    "computable-expressions",
+    "-p",
+    "numbers",
+    "--diff-columns",
+    "1,2",
+    "--diff-only",
+    "--format",
+    "jsonl",
+  ]);
+  let output = String::from_utf8(cmd.assert().success().get_output().stdout.clone())?;
+  // Numbers are left out, so only lines where the first two disagree remain
+  let lines: Vec<u64> = output
+    .lines()
+    .skip(1)
+    .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["line"].as_u64().unwrap())
+    .collect();
+  assert_eq!(lines, [2, 3, 4, 8, 9, 10]);
+  Ok(())
+}
+
+#[test]
+fn diff_against_baseline() -> Result<(), Box<dyn std::error::Error>> {
+  let mut cmd = anno();
+  cmd.args([
+    "example.c",
+    "-p",
+    "computable-expressions",
+    "-p",
+    "dwarf-line-table",
+    "-p",
+    "computable-expressions",
+    "--diff-against",
+    "1",
+    "--format",
+    "html",
+  ]);
+  // Each column differing from the baseline is highlighted
+  cmd.assert().success().stdout(predicate::str::contains(
+    "<td class=\"annotation diff-after\">x</td><td class=\"annotation diff-before\"> </td>\
+     <td class=\"annotation diff-after\">x</td><td>  int result = x * x;</td>",
+  ));
+  Ok(())
+}
+
+#[test]
+fn diff_column_out_of_range() -> Result<(), Box<dyn std::error::Error>> {
+  let mut cmd = anno();
+  cmd.args(["example.c", "-p", "numbers", "-p", "numbers", "--diff-columns", "2"]);
+  cmd.assert().failure().stderr(predicate::str::contains(
+    "Cannot diff column 2, as there are only 2 producers (columns are numbered from 0)",
+  ));
+  Ok(())
+}
+
+#[test]
+fn no_producers_without_diff() -> Result<(), Box<dyn std::error::Error>> {
+  let mut cmd = anno();
+  cmd.args(["example.c", "--lines", "1"]);
+  cmd
+    .assert()
+    .success()
+    .stdout("\n#include <stdio.h>\n")
+    .stderr(predicate::str::contains("Warning: No producers"));
+  Ok(())
+}
+
+#[test]
+fn no_producers_to_diff() -> Result<(), Box<dyn std::error::Error>> {
+  // With nothing to compare, diffing has no effect
+  let mut cmd = anno();
+  cmd.args(["example.c", "--lines", "1", "--diff"]);
+  cmd.assert().success().stdout("\n#include <stdio.h>\n");
+
+  // An explicit baseline must still exist
+  let mut cmd = anno();
+  cmd.args(["example.c", "--diff-against", "0"]);
+  cmd
+    .assert()
+    .failure()
+    .stderr(predicate::str::contains("Cannot diff column 0, as there are only 0 producers"));
+  Ok(())
+}
+
+#[test]
+fn diff_sets() -> Result<(), Box<dyn std::error::Error>> {
+  let mut cmd = anno();
+  cmd.args([
+    "example.c",
+    "-p",
+    "cat:output/vars-before.jsonl",
+    "-p",
+    "cat:output/vars-after.jsonl",
+    "--diff",
+    "--color",
+    "never",
+  ]);
+  cmd
+    .assert()
+    .success()
+    // Order of elements is ignored
+    .stdout(predicate::str::contains("result x | x result       |   int result = x * x;\n"))
+    // Missing and extra elements are shown in place of the value
+    .stdout(predicate::str::contains("result   | -result        |   return result;\n"))
+    .stdout(predicate::str::contains("value    | +square +x     |   int value = square(7);\n"));
+  Ok(())
+}
+
+#[test]
+fn diff_sets_json() -> Result<(), Box<dyn std::error::Error>> {
+  let mut cmd = anno();
+  cmd.args([
+    "example.c",
+    "-p",
+    "cat:output/vars-before.jsonl",
+    "-p",
+    "cat:output/vars-after.jsonl",
+    "--diff-only",
+    "--format",
+    "json",
+  ]);
+  let output = cmd.assert().success().get_output().stdout.clone();
+  let document: serde_json::Value = serde_json::from_slice(&output)?;
+  let lines = document["lines"].as_array().unwrap();
+  assert_eq!(lines.len(), 2);
+  assert_eq!(
+    lines[1]["annotations"]["cat:output/vars-after.jsonl"]["elements"],
+    serde_json::json!(["square", "value", "x"])
+  );
+  assert_eq!(
+    lines[1]["changes"]["cat:output/vars-after.jsonl"],
+    serde_json::json!({ "missing": [], "extra": ["square", "x"] })
+  );
+  Ok(())
+}
+
+#[test]
+fn diff_only_context() -> Result<(), Box<dyn std::error::Error>> {
+  let mut cmd = anno();
+  cmd.args([
+    "example.c",
+    "-p",
+    "computable-expressions",
+    "-p",
+    "dwarf-line-table",
+    "--diff-only",
+    "-C",
+    "1",
+    "--color",
+    "never",
+  ]);
+  // Line numbers are shown even without the `numbers` producer
+  cmd.assert().success().stdout(
+    "   | … | … | \n \
+     1 |   |   | #include <stdio.h>\n \
+     2 | x |   | \n \
+     3 |   | x | int square(int x) {\n \
+     4 | x |   |   int result = x * x;\n \
+     5 |   |   |   return result;\n\
+     ... (line 6 skipped)\n \
+     7 |   |   | \n \
+     8 | x |   | int main(void) {\n \
+     9 |   | x |   int value = square(7);\n\
+     10 | x |   |   printf(\"%d\\n\", value);\n\
+     11 |   |   |   return 0;\n\
+     ... (line 12 skipped)\n",
+  );
+  Ok(())
+}
+
+#[test]
+fn diff_only_skipped_lines() -> Result<(), Box<dyn std::error::Error>> {
+  let mut cmd = anno();
+  cmd.args([
+    "example.c",
+    "-p",
+    "computable-expressions",
+    "-p",
+    "dwarf-line-table",
+    "--diff-only",
+    "--color",
+    "never",
+  ]);
+  cmd
+    .assert()
+    .success()
+    .stdout(predicate::str::contains("... (line 1 skipped)\n 2 | x |"))
+    .stdout(predicate::str::contains(
+      "... (lines 5-7 skipped)\n 8 | x |   | int main(void) {\n",
+    ))
+    .stdout(predicate::str::ends_with("... (lines 11-12 skipped)\n"));
+  Ok(())
+}
+
+#[test]
+fn diff_skips_undiffable_columns() -> Result<(), Box<dyn std::error::Error>> {
+  // `numbers` declares it cannot be diffed, so is neither the baseline nor
+  // compared with it unless chosen explicitly
+  let mut cmd = anno();
+  cmd.args([
+    "example.c",
+    "-p",
+    "numbers",
+    "-p",
+    "computable-expressions",
+    "-p",
+    "dwarf-line-table",
+    "--diff-only",
+    "--color",
+    "never",
+  ]);
+  cmd
+    .assert()
+    .success()
+    .stdout(predicate::str::starts_with("   | n… | … | … | \n... (line 1 skipped)\n 2 | 2  | x |   | \n"))
+    .stdout(predicate::str::contains("... (lines 5-7 skipped)\n"));
+
+  let mut cmd = anno();
+  cmd.args(["example.c", "-p", "numbers", "-p", "computable-expressions", "--diff-columns", "0"]);
+  cmd.args(["--diff-against", "1", "--diff-only", "--color", "never"]);
+  cmd
+    .assert()
+    .success()
+    .stdout(predicate::str::starts_with("   | n… | … | \n 1 | 1  |   | #include <stdio.h>\n"));
+  Ok(())
+}
@@ -0,0 +1,12 @@
+#include <stdio.h>
+
+int square(int x) {
+  int result = x * x;
+  return result;
+}
+
+int main(void) {
+  int value = square(7);
+  printf("%d\n", value);
+  return 0;
+}
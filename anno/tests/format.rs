@@ -0,0 +1,258 @@
+use assert_cmd::prelude::*;
+use predicates::prelude::*;
+use serde_json::{json, Value};
+
+mod common;
+
+use common::anno;
+
+#[test]
+fn json_format() -> Result<(), Box<dyn std::error::Error>> {
+  let mut cmd = anno();
+  cmd.args([
+    "example.c",
+    "-p",
+    "numbers:",
+    "-p",
+    "cat:output/sparse.jsonl",
+    "--format",
+    "json",
+  ]);
+  let output = cmd.assert().success().get_output().stdout.clone();
+  let document: Value = serde_json::from_slice(&output)?;
+  assert_eq!(document["target"], "example.c");
+  assert_eq!(document["line_count"], 12);
+  assert_eq!(
+    document["producers"],
+    json!([
+      { "key": "numbers", "name": "numbers", "uri": "numbers:" },
+      { "key": "cat", "name": "cat", "uri": "cat:output/sparse.jsonl" },
+    ])
+  );
+  let lines = document["lines"].as_array().unwrap();
+  assert_eq!(lines.len(), 12);
+  assert_eq!(
+    lines[2],
+    json!({
+      "line": 3,
+      "text": "int square(int x) {",
+      "annotations": {
+        "numbers": { "value": "3" },
+        "cat": { "value": "x z", "detail": "Function start" },
+      },
+    })
+  );
+  assert_eq!(
+    lines[8]["annotations"]["cat"],
+    json!({ "value": "y", "severity": "warning" })
+  );
+  Ok(())
+}
+
+#[test]
+fn jsonl_format() -> Result<(), Box<dyn std::error::Error>> {
+  let mut cmd = anno();
+  cmd.args([
+    "example.c",
+    "-p",
+    "cat:output/ranges.jsonl",
+    "--format",
+    "jsonl",
+  ]);
+  let output = cmd.assert().success().get_output().stdout.clone();
+  let records: Vec<Value> = String::from_utf8(output)?
+    .lines()
+    .map(serde_json::from_str)
+    .collect::<Result<_, _>>()?;
+  assert_eq!(records.len(), 13);
+  assert_eq!(records[0]["line_count"], 12);
+  assert_eq!(
+    records[4],
+    json!({
+      "line": 4,
+      "text": "  int result = x * x;",
+      "annotations": {
+        "cat": {
+          "value": "x f",
+          "spans": [{ "start": 16, "end": 20 }, { "start": 18, "end": 18 }],
+        },
+      },
+    })
+  );
+  Ok(())
+}
+
+#[test]
+fn json_format_diff_only() -> Result<(), Box<dyn std::error::Error>> {
+  let mut cmd = anno();
+  cmd.args([
+    "example.c",
+    "-p",
+    "computable-expressions:",
+    "-p",
+    "dwarf-line-table:",
+    "--diff-only",
+    "--format",
+    "json",
+  ]);
+  let output = cmd.assert().success().get_output().stdout.clone();
+  let document: Value = serde_json::from_slice(&output)?;
+  let line_numbers: Vec<&Value> = document["lines"]
+    .as_array()
+    .unwrap()
+    .iter()
+    .map(|line| {
+      assert_eq!(line["differs"], true);
+      &line["line"]
+    })
+    .collect();
+  assert_eq!(line_numbers, [2, 3, 4, 8, 9, 10]);
+  Ok(())
+}
+
+#[test]
+fn json_format_repeated_producers() -> Result<(), Box<dyn std::error::Error>> {
+  let mut cmd = anno();
+  cmd.args([
+    "example.c",
+    "-p",
+    "sleep:0",
+    "-p",
+    "sleep:0.1",
+    "-p",
+    "numbers:",
+    "-p",
+    "numbers:",
+    "--format",
+    "json",
+  ]);
+  let output = cmd.assert().success().get_output().stdout.clone();
+  let document: Value = serde_json::from_slice(&output)?;
+  let keys: Vec<&Value> = document["producers"]
+    .as_array()
+    .unwrap()
+    .iter()
+    .map(|producer| &producer["key"])
+    .collect();
+  assert_eq!(keys, ["sleep:0", "sleep:0.1", "numbers:#1", "numbers:#2"]);
+  Ok(())
+}
+
+#[test]
+fn html_format() -> Result<(), Box<dyn std::error::Error>> {
+  let mut cmd = anno();
+  cmd.args([
+    "example.c",
+    "-p",
+    "computable-expressions:",
+    "-p",
+    "dwarf-line-table:",
+    "-p",
+    "cat:output/long.jsonl",
+    "--diff",
+    "--format",
+    "html",
+  ]);
+  let output = String::from_utf8(cmd.assert().success().get_output().stdout.clone())?;
+  assert!(output.starts_with("<!DOCTYPE html>"));
+  // Self-contained, with sticky headers
+  assert!(!output.contains("<link"));
+  assert!(!output.contains("<script"));
+  assert!(output.contains("th { position: sticky; top: 0;"));
+  assert!(output.contains(
+    "<th class=\"annotation\" title=\"computable-expressions:\">computable-expressions</th>"
+  ));
+  // Per-line anchors, escaped source, and diff highlighting
+  assert!(output.contains(
+    "<tr id=\"L1\"><td class=\"number\"><a href=\"#L1\">1</a></td>\
+     <td class=\"annotation\"> </td><td class=\"annotation\"> </td>\
+     <td class=\"annotation\"></td><td>#include &lt;stdio.h&gt;</td></tr>"
+  ));
+  assert!(output.contains(
+    "<tr id=\"L2\"><td class=\"number\"><a href=\"#L2\">2</a></td>\
+     <td class=\"annotation diff-before\">x</td><td class=\"annotation diff-after\"> </td>"
+  ));
+  // Tooltips for truncated annotations and details
+  assert!(output.contains(
+    "<span class=\"truncated\" \
+     title=\"result_with_a_particularly_long_name other\n\nDefined &lt;here&gt;\">\
+     result_with_a_particularly_lo…</span>"
+  ));
+  assert!(output.contains("<span class=\"detail\" title=\"Short &amp; sweet\">value</span>"));
+  Ok(())
+}
+
+#[test]
+fn numbers_aligned_with_legend() -> Result<(), Box<dyn std::error::Error>> {
+  let mut cmd = anno();
+  cmd.args(["example.c", "-p", "lengths", "-p", "dwarf-line-table", "--legend", "--color", "never"]);
+  cmd.assert().success().stdout(predicate::str::starts_with(
+    "lengths: 0 = Empty line\n\
+     dwarf-line-table: x = In line table\n\
+     l… | … | \n\
+     18 |   | #include <stdio.h>\n \
+     0 |   | \n\
+     19 | x | int square(int x) {\n",
+  ));
+
+  let mut cmd = anno();
+  cmd.args(["example.c", "-p", "lengths", "-p", "dwarf-line-table", "--format", "html"]);
+  cmd
+    .assert()
+    .success()
+    .stdout(predicate::str::contains(
+      "<th class=\"annotation right\" title=\"lengths:\n0: Empty line\">lengths</th>",
+    ))
+    .stdout(predicate::str::contains("<td class=\"annotation right\">18</td>"));
+  Ok(())
+}
+
+#[test]
+fn wide_annotations_fit_display_width() -> Result<(), Box<dyn std::error::Error>> {
+  let mut cmd = anno();
+  cmd.args([
+    "example.c",
+    "-p",
+    "cat:output/wide.jsonl",
+    "-p",
+    "numbers:",
+    "--max-width",
+    "cat=8",
+    "--color",
+    "never",
+  ]);
+  cmd.assert().success().stdout(predicate::str::starts_with(
+    "cat      | n… | \n\
+     日本語   | 1  | #include <stdio.h>\n         \
+     | 2  | \n\
+     x        | 3  | int square(int x) {\n\
+     result_… | 4  |   int result = x * x;\n",
+  ));
+  Ok(())
+}
+
+#[test]
+fn wrapped_annotations() -> Result<(), Box<dyn std::error::Error>> {
+  let mut cmd = anno();
+  cmd.args([
+    "example.c",
+    "-p",
+    "cat:output/wide.jsonl",
+    "-p",
+    "numbers:",
+    "--max-width",
+    "12",
+    "--wrap",
+    "--color",
+    "never",
+  ]);
+  cmd.assert().success().stdout(predicate::str::contains(
+    "x            | 3  | int square(int x) {\n\
+     result_with_ | 4  |   int result = x * x;\n\
+     a_particular |    | \n\
+     ly_long_name |    | \n\
+     other        |    | \n\
+     \x20            | 5  |   return result;\n",
+  ));
+  Ok(())
+}
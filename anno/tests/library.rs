@@ -0,0 +1,100 @@
+use std::{env, io::Write, path::Path, sync::Once};
+
+use anno::{describe::ValueKind, DiffOptions, Format, Producer, Renderer, Session, Table};
+
+mod common;
+
+use common::{fixtures, test_path};
+
+/// Makes test producers available in `PATH` for this test process
+fn setup() {
+  static SETUP: Once = Once::new();
+  SETUP.call_once(|| {
+    env::set_var("PATH", test_path());
+  });
+}
+
+#[test]
+fn session_aggregates_annotations() -> Result<(), Box<dyn std::error::Error>> {
+  setup();
+  let session = Session::new(vec![
+    Producer::parse("computable-expressions:")?,
+    Producer::parse("dwarf-line-table:")?,
+  ]);
+  let annotated = session.annotate(&fixtures().join("example.c"))?;
+  assert_eq!(annotated.annotations.len(), 2);
+  assert_eq!(annotated.annotations[0].lines[1].value, "x");
+  assert_eq!(annotated.annotations[1].lines[2].value, "x");
+
+  let table = annotated.table(DiffOptions {
+    only: true,
+    ..Default::default()
+  });
+  let lines: Vec<usize> = table.rows.iter().map(|row| row.number).collect();
+  assert_eq!(lines, [2, 3, 4, 8, 9, 10]);
+  Ok(())
+}
+
+#[test]
+fn session_annotates_content() -> Result<(), Box<dyn std::error::Error>> {
+  setup();
+  let session = Session::new(vec![Producer::parse("numbers:")?]);
+  let annotated = session.annotate_content(Path::new("buffer.c"), "a\nb\n".to_string())?;
+  let table = annotated.table(DiffOptions::default());
+  let mut output = Vec::new();
+  Format::Terminal.renderer().render(&table, &mut output)?;
+  assert_eq!(String::from_utf8(output)?, "… | \n1 | a\n2 | b\n");
+  Ok(())
+}
+
+/// Renderer listing annotated line numbers only
+struct LineNumbers;
+
+impl Renderer for LineNumbers {
+  fn render(&self, table: &Table, out: &mut dyn Write) -> anyhow::Result<()> {
+    for row in &table.rows {
+      if row.annotations.iter().any(|a| !a.value.trim().is_empty()) {
+        writeln!(out, "{}", row.number)?;
+      }
+    }
+    Ok(())
+  }
+}
+
+#[test]
+fn custom_renderer() -> Result<(), Box<dyn std::error::Error>> {
+  setup();
+  let session = Session::new(vec![Producer::parse("dwarf-line-table:")?]);
+  let annotated = session.annotate(&fixtures().join("example.c"))?;
+  let mut output = Vec::new();
+  LineNumbers.render(&annotated.table(DiffOptions::default()), &mut output)?;
+  assert_eq!(String::from_utf8(output)?, "3\n6\n9\n12\n");
+  Ok(())
+}
+
+#[test]
+fn session_applies_capabilities() -> Result<(), Box<dyn std::error::Error>> {
+  setup();
+  let mut session = Session::new(vec![
+    Producer::parse("numbers:")?,
+    Producer::parse("env:?var=ANNO_TEST_A")?,
+    Producer::parse("env:?var=ANNO_TEST_B")?,
+  ]);
+  session.env.insert("ANNO_TEST_A".to_string(), "x".to_string());
+  session.env.insert("ANNO_TEST_B".to_string(), "y".to_string());
+  session.describe_producers()?;
+  assert!(!session.capabilities[0].diffable);
+  assert_eq!(session.default_baseline(), 1);
+
+  // Marks are only compared by whether they are present
+  session.capabilities[1].kind = Some(ValueKind::Boolean);
+  session.capabilities[2].kind = Some(ValueKind::Boolean);
+  let annotated = session.annotate(&fixtures().join("example.c"))?;
+  let table = annotated.table(DiffOptions {
+    only: true,
+    baseline: session.default_baseline(),
+    ..Default::default()
+  });
+  assert!(table.rows.is_empty());
+  Ok(())
+}
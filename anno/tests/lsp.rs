@@ -0,0 +1,142 @@
+use std::{
+  io::{BufRead, BufReader, Read, Write},
+  process::{ChildStdin, ChildStdout, Stdio},
+};
+
+use serde_json::{json, Value};
+
+mod common;
+
+use common::{anno, fixtures};
+
+/// Minimal LSP client speaking to `anno lsp` over stdio
+struct Client {
+  stdin: ChildStdin,
+  stdout: BufReader<ChildStdout>,
+}
+
+impl Client {
+  fn send(&mut self, message: Value) {
+    let body = message.to_string();
+    write!(self.stdin, "Content-Length: {}\r\n\r\n{}", body.len(), body).unwrap();
+    self.stdin.flush().unwrap();
+  }
+
+  fn request(&mut self, id: u64, method: &str, params: Value) -> Value {
+    self.send(json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }));
+    loop {
+      let message = self.receive();
+      if message["id"] == id {
+        return message["result"].clone();
+      }
+    }
+  }
+
+  fn notify(&mut self, method: &str, params: Value) {
+    self.send(json!({ "jsonrpc": "2.0", "method": method, "params": params }));
+  }
+
+  /// Waits for a notification with the given method, skipping anything else
+  fn notification(&mut self, method: &str) -> Value {
+    loop {
+      let message = self.receive();
+      if message["method"] == method {
+        return message["params"].clone();
+      }
+    }
+  }
+
+  fn receive(&mut self) -> Value {
+    let mut length = 0;
+    loop {
+      let mut header = String::new();
+      self.stdout.read_line(&mut header).unwrap();
+      let header = header.trim_end();
+      if header.is_empty() {
+        break;
+      }
+      if let Some(value) = header.strip_prefix("Content-Length: ") {
+        length = value.parse().unwrap();
+      }
+    }
+    let mut body = vec![0; length];
+    self.stdout.read_exact(&mut body).unwrap();
+    serde_json::from_slice(&body).unwrap()
+  }
+}
+
+#[test]
+fn lsp_session() -> Result<(), Box<dyn std::error::Error>> {
+  let mut child = anno()
+    .args(["lsp", "-p", "cat:output/sparse.jsonl", "--diagnostics", "--code-lens"])
+    .stdin(Stdio::piped())
+    .stdout(Stdio::piped())
+    .spawn()?;
+  let mut client = Client {
+    stdin: child.stdin.take().unwrap(),
+    stdout: BufReader::new(child.stdout.take().unwrap()),
+  };
+
+  let initialized = client.request(1, "initialize", json!({ "capabilities": {} }));
+  assert_eq!(initialized["capabilities"]["inlayHintProvider"], true);
+  assert_eq!(initialized["capabilities"]["hoverProvider"], true);
+  client.notify("initialized", json!({}));
+
+  let uri = format!("file://{}", fixtures().join("example.c").display());
+  let document = json!({ "uri": uri });
+  client.notify(
+    "textDocument/didOpen",
+    json!({
+      "textDocument": { "uri": uri, "languageId": "c", "version": 1, "text": "" },
+    }),
+  );
+
+  // Only annotations with a severity become diagnostics
+  let diagnostics = client.notification("textDocument/publishDiagnostics");
+  assert_eq!(
+    diagnostics["diagnostics"],
+    json!([{
+      "range": { "start": { "line": 8, "character": 0 }, "end": { "line": 8, "character": 24 } },
+      "severity": 2,
+      "source": "anno",
+      "message": "cat: y",
+    }])
+  );
+
+  let hints = client.request(
+    2,
+    "textDocument/inlayHint",
+    json!({
+      "textDocument": document,
+      "range": { "start": { "line": 0, "character": 0 }, "end": { "line": 11, "character": 0 } },
+    }),
+  );
+  let hints: Vec<(u64, u64, &str)> = hints
+    .as_array()
+    .unwrap()
+    .iter()
+    .map(|hint| {
+      (
+        hint["position"]["line"].as_u64().unwrap(),
+        hint["position"]["character"].as_u64().unwrap(),
+        hint["label"].as_str().unwrap(),
+      )
+    })
+    .collect();
+  assert_eq!(hints, [(2, 19, "cat: x z"), (8, 24, "cat: y")]);
+
+  let hover = client.request(
+    3,
+    "textDocument/hover",
+    json!({ "textDocument": document, "position": { "line": 2, "character": 4 } }),
+  );
+  assert_eq!(hover["contents"]["value"], "**cat**: `x z`\n\nFunction start");
+
+  let lenses = client.request(4, "textDocument/codeLens", json!({ "textDocument": document }));
+  assert_eq!(lenses[0]["command"]["title"], "cat: 2 of 12 lines");
+
+  client.request(5, "shutdown", Value::Null);
+  client.notify("exit", Value::Null);
+  assert!(child.wait()?.success());
+  Ok(())
+}
@@ -0,0 +1,173 @@
+use std::fs;
+
+use assert_cmd::prelude::*;
+use predicates::prelude::*;
+
+mod common;
+
+use common::anno;
+
+#[test]
+fn multiple_files_in_sections() -> Result<(), Box<dyn std::error::Error>> {
+  let dir = tempfile::tempdir()?;
+  fs::write(dir.path().join("a.c"), "a\n")?;
+  fs::write(dir.path().join("b.c"), "b\nb\n")?;
+
+  let mut cmd = anno();
+  cmd.current_dir(dir.path());
+  cmd.args(["a.c", "b.c", "-p", "numbers", "--color", "never"]);
+  cmd
+    .assert()
+    .success()
+    .stdout("==> a.c <==\n… | \n1 | a\n\n==> b.c <==\n… | \n1 | b\n2 | b\n");
+  Ok(())
+}
+
+#[test]
+fn multiple_directory_expanded() -> Result<(), Box<dyn std::error::Error>> {
+  let dir = tempfile::tempdir()?;
+  fs::create_dir_all(dir.path().join("src/nested"))?;
+  fs::create_dir_all(dir.path().join(".git"))?;
+  fs::write(dir.path().join("src/main.c"), "a\n")?;
+  fs::write(dir.path().join("src/nested/util.c"), "b\n")?;
+  fs::write(dir.path().join("src/nested/util.h"), "c\n")?;
+  fs::write(dir.path().join("src/.hidden.c"), "d\n")?;
+  fs::write(dir.path().join("src/binary.o"), [0xff, 0xfe, 0x00])?;
+  fs::write(dir.path().join(".git/config"), "e\n")?;
+
+  // Hidden and binary files are left out
+  let mut cmd = anno();
+  cmd.current_dir(dir.path());
+  cmd.args([".", "-p", "numbers", "--format", "jsonl"]);
+  let output = cmd.assert().success().get_output().stdout.clone();
+  let targets: Vec<String> = String::from_utf8(output)?
+    .lines()
+    .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+    .filter_map(|record| record["target"].as_str().map(String::from))
+    .collect();
+  assert_eq!(targets, ["./src/main.c", "./src/nested/util.c", "./src/nested/util.h"]);
+
+  let mut cmd = anno();
+  cmd.current_dir(dir.path());
+  cmd.args(["src/**/*.c", "-p", "numbers", "--format", "jsonl"]);
+  let output = cmd.assert().success().get_output().stdout.clone();
+  let targets: Vec<String> = String::from_utf8(output)?
+    .lines()
+    .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+    .filter_map(|record| record["target"].as_str().map(String::from))
+    .collect();
+  assert_eq!(targets, ["src/main.c", "src/nested/util.c"]);
+  Ok(())
+}
+
+#[test]
+fn multiple_pattern_sniffs_large_files() -> Result<(), Box<dyn std::error::Error>> {
+  let dir = tempfile::tempdir()?;
+  fs::create_dir_all(dir.path().join("src/nested"))?;
+  // Only the start of each file is checked, even if a character is cut off
+  fs::write(dir.path().join("src/large.c"), format!("a{}", "\u{e9}".repeat(10_000)))?;
+  fs::write(dir.path().join("src/nested/util.c"), "b\n")?;
+
+  // A single `*` stays within one directory
+  let mut cmd = anno();
+  cmd.current_dir(dir.path());
+  cmd.args(["src/*.c", "-p", "numbers", "--format", "jsonl"]);
+  let output = cmd.assert().success().get_output().stdout.clone();
+  let targets: Vec<String> = String::from_utf8(output)?
+    .lines()
+    .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+    .filter_map(|record| record["target"].as_str().map(String::from))
+    .collect();
+  assert_eq!(targets, ["src/large.c"]);
+  Ok(())
+}
+
+#[test]
+fn multiple_producers_run_once() -> Result<(), Box<dyn std::error::Error>> {
+  let dir = tempfile::tempdir()?;
+  fs::write(dir.path().join("a.c"), "a\n")?;
+  fs::write(dir.path().join("b.c"), "b\n")?;
+  fs::write(dir.path().join("c.c"), "c\n")?;
+  let targets_runs = dir.path().join("targets-runs");
+  let runs = dir.path().join("runs");
+
+  let mut cmd = anno();
+  cmd.current_dir(dir.path());
+  cmd.args(["a.c", "b.c", "c.c", "--format", "json", "-p"]);
+  cmd.arg(format!("targets:?file={}", targets_runs.display()));
+  cmd.arg("-p");
+  cmd.arg(format!("runs:?file={}", runs.display()));
+  let output = cmd.assert().success().get_output().stdout.clone();
+  let output: serde_json::Value = serde_json::from_slice(&output)?;
+
+  // Producers annotating every target are run once, others once per target
+  assert_eq!(fs::read_to_string(&targets_runs)?.lines().count(), 1);
+  assert_eq!(fs::read_to_string(&runs)?.lines().count(), 3);
+  let files = output["files"].as_array().unwrap();
+  assert_eq!(files.len(), 3);
+  for (file, name) in files.iter().zip(["a.c", "b.c", "c.c"]) {
+    assert_eq!(file["target"], name);
+    assert_eq!(file["lines"][0]["annotations"]["targets"]["value"], name);
+  }
+  let index = output["index"].as_array().unwrap();
+  assert_eq!(index[1]["target"], "b.c");
+  assert_eq!(index[1]["line_count"], 1);
+  assert_eq!(index[1]["producers"][0]["annotated_lines"], 1);
+  Ok(())
+}
+
+#[test]
+fn multiple_html_index() -> Result<(), Box<dyn std::error::Error>> {
+  let mut cmd = anno();
+  cmd.args(["example.c", "project/example.c", "-p", "numbers", "--format", "html"]);
+  cmd
+    .assert()
+    .success()
+    .stdout(predicate::str::contains("<h1>2 files</h1>"))
+    .stdout(predicate::str::contains(
+      "<tr><td><a href=\"#F2\">project/example.c</a></td><td class=\"count\">12</td><td class=\"count\">12</td></tr>",
+    ))
+    .stdout(predicate::str::contains("<h2 id=\"F2\">project/example.c</h2>"))
+    .stdout(predicate::str::contains("<tr id=\"F2-L3\">"));
+  Ok(())
+}
+
+#[test]
+fn multiple_functions_found_in_any_file() -> Result<(), Box<dyn std::error::Error>> {
+  let dir = tempfile::tempdir()?;
+  fs::write(dir.path().join("a.c"), "int a() {\n  return 1;\n}\n")?;
+  fs::write(dir.path().join("b.c"), "int b() {\n  return 2;\n}\n")?;
+
+  let mut cmd = anno();
+  cmd.current_dir(dir.path());
+  cmd.args(["a.c", "b.c", "--function", "b", "-p", "numbers", "--color", "never"]);
+  cmd
+    .assert()
+    .success()
+    .stdout("==> b.c <==\n… | \n1 | int b() {\n2 |   return 2;\n3 | }\n");
+
+  let mut cmd = anno();
+  cmd.current_dir(dir.path());
+  cmd.args(["a.c", "b.c", "--function", "c", "-p", "numbers"]);
+  cmd.assert().failure().stderr(predicate::str::contains(
+    "Unable to find a definition of function `c` in the files being annotated",
+  ));
+  Ok(())
+}
+
+#[test]
+fn multiple_single_file_options() -> Result<(), Box<dyn std::error::Error>> {
+  let mut cmd = anno();
+  cmd.args(["example.c", "project/example.c", "--watch"]);
+  cmd.assert().failure().stderr(predicate::str::contains(
+    "`--watch` can only be used with a single file",
+  ));
+
+  let mut cmd = anno();
+  cmd.args(["project/*.rs"]);
+  cmd
+    .assert()
+    .failure()
+    .stderr(predicate::str::contains("No files match `project/*.rs`"));
+  Ok(())
+}
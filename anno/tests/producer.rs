@@ -1,9 +1,15 @@
+use std::{fs, os::unix::fs::PermissionsExt};
+
 use assert_cmd::prelude::*;
-use std::process::Command;
+use predicates::prelude::*;
+
+mod common;
+
+use common::anno;
 
 #[test]
 fn producer_only() -> Result<(), Box<dyn std::error::Error>> {
-  let mut cmd = Command::cargo_bin("anno")?;
+  let mut cmd = anno();
   cmd.args(["example.c", "-p", "computable-expressions:"]);
   cmd.assert().success();
   Ok(())
@@ -11,7 +17,7 @@ fn producer_only() -> Result<(), Box<dyn std::error::Error>> {
 
 #[test]
 fn producer_with_source() -> Result<(), Box<dyn std::error::Error>> {
-  let mut cmd = Command::cargo_bin("anno")?;
+  let mut cmd = anno();
   cmd.args(["example.c", "-p", "dwarf-line-table:/example.dwarf"]);
   cmd.assert().success();
   Ok(())
@@ -19,8 +25,141 @@ fn producer_with_source() -> Result<(), Box<dyn std::error::Error>> {
 
 #[test]
 fn producer_with_source_and_params() -> Result<(), Box<dyn std::error::Error>> {
-  let mut cmd = Command::cargo_bin("anno")?;
+  let mut cmd = anno();
   cmd.args(["example.c", "-p", "dwarf-line-table:/example.dwarf?function=bob"]);
   cmd.assert().success();
   Ok(())
 }
+
+#[test]
+fn params_passed_to_producer() -> Result<(), Box<dyn std::error::Error>> {
+  let mut cmd = anno();
+  cmd.args([
+    "example.c",
+    "-p",
+    "params:?function=bob&function=alice&max-depth=2",
+    "--format",
+    "jsonl",
+  ]);
+  cmd.assert().success().stdout(predicate::str::contains(
+    r#""value":"bob,alice {\"function\":[\"bob\",\"alice\"],\"max-depth\":[\"2\"]}""#,
+  ));
+  Ok(())
+}
+
+#[test]
+fn producer_without_colon() -> Result<(), Box<dyn std::error::Error>> {
+  let mut cmd = anno();
+  cmd.args(["example.c", "-p", "numbers"]);
+  cmd.assert().success().stdout(predicate::str::contains("12 | }"));
+  Ok(())
+}
+
+#[test]
+fn producer_executable_path() -> Result<(), Box<dyn std::error::Error>> {
+  let mut cmd = anno();
+  cmd.args(["example.c", "-p", "./bin/anno-numbers:", "-p", "./bin/anno-missing:"]);
+  cmd
+    .assert()
+    .failure()
+    .stderr(predicate::str::contains("Annotation producer `./bin/anno-missing` could not be run"));
+  Ok(())
+}
+
+#[test]
+fn producer_path_option() -> Result<(), Box<dyn std::error::Error>> {
+  let mut cmd = anno();
+  cmd.args(["example.c", "-p", "lines", "--producer-path", "lines=bin/anno-numbers"]);
+  cmd.assert().success().stdout(predicate::str::contains("12 | }"));
+  Ok(())
+}
+
+#[test]
+fn producer_invalid() -> Result<(), Box<dyn std::error::Error>> {
+  let mut cmd = anno();
+  cmd.args(["example.c", "-p", ":/example.dwarf"]);
+  cmd.assert().failure().stderr(predicate::str::contains(
+    "Invalid producer `:/example.dwarf`: missing producer name before `:`",
+  ));
+  let mut cmd = anno();
+  cmd.args(["example.c", "-p", "dwarf line table"]);
+  cmd.assert().failure().stderr(predicate::str::contains(
+    "producer name `dwarf line table` contains ` `",
+  ));
+  Ok(())
+}
+
+#[test]
+fn producers_listed() -> Result<(), Box<dyn std::error::Error>> {
+  let mut cmd = anno();
+  cmd.args(["producers", "list"]);
+  cmd
+    .assert()
+    .success()
+    .stdout(predicate::str::contains(
+      "params\n  Reports query parameters\n  Data source: not used\n  Parameters:\n    function: Function to report\n    max-depth\n  Protocols: lines\n",
+    ))
+    .stdout(predicate::str::contains(
+      "required\n  Numbers lines given a source and mode\n  Data source: required (Any file)\n  Parameters:\n    mode (required)\n",
+    ))
+    .stdout(predicate::str::contains("cat\n  (does not describe itself)\n"));
+
+  let mut cmd = anno();
+  cmd.args(["producers", "list", "--json"]);
+  let output = cmd.assert().success().get_output().stdout.clone();
+  let producers: serde_json::Value = serde_json::from_slice(&output)?;
+  let sleep = producers.as_array().unwrap().iter().find(|p| p["name"] == "sleep").unwrap();
+  assert!(sleep["path"].as_str().unwrap().ends_with("bin/anno-sleep"));
+  assert_eq!(sleep["description"]["source"], "optional");
+  assert_eq!(sleep["description"]["protocols"][0], "lines");
+  Ok(())
+}
+
+#[test]
+fn producer_specs_checked_before_running() -> Result<(), Box<dyn std::error::Error>> {
+  let dir = tempfile::tempdir()?;
+  let runs = dir.path().join("runs");
+
+  let mut cmd = anno();
+  cmd.args(["example.c", "-p", "required", "-p", "params:x?depth=1", "-p"]);
+  cmd.arg(format!("runs:?file={}", runs.display()));
+  cmd.assert().failure().stderr(predicate::str::contains(
+    "4 problems with producers:
+  - `required:` requires a data source (Any file)
+  - `required:` requires parameter `mode`
+  - `params:x?depth=1` does not take a data source
+  - `params:x?depth=1` has no parameter `depth` (accepted: `function`, `max-depth`)",
+  ));
+  assert!(!runs.exists());
+
+  let mut cmd = anno();
+  cmd.args(["example.c", "-p", "required:example.c?mode=a"]);
+  cmd.assert().success().stdout(predicate::str::contains("12 | }"));
+  Ok(())
+}
+
+#[test]
+fn producer_dirs_from_config() -> Result<(), Box<dyn std::error::Error>> {
+  let dir = tempfile::tempdir()?;
+  fs::create_dir(dir.path().join("tools"))?;
+  fs::write(dir.path().join("anno.toml"), "producer-dirs = [\"tools\"]\n")?;
+  fs::write(dir.path().join("target.c"), "a\nb\n")?;
+  let producer = dir.path().join("tools/anno-local");
+  fs::write(&producer, "#!/bin/sh\nyes local | head -n \"$ANNO_TARGET_LINES\"\n")?;
+  fs::set_permissions(&producer, fs::Permissions::from_mode(0o755))?;
+
+  let mut cmd = anno();
+  cmd.current_dir(dir.path());
+  cmd.args(["target.c", "-p", "local", "--color", "never"]);
+  cmd.assert().success().stdout("local | \nlocal | a\nlocal | b\n");
+
+  let mut cmd = anno();
+  cmd.current_dir(dir.path());
+  cmd.args(["producers", "list"]);
+  cmd
+    .assert()
+    .success()
+    .stdout(predicate::str::contains("local\n  (does not describe itself)\n  Path: "))
+    .stdout(predicate::str::contains("tools/anno-local\n"));
+  Ok(())
+}
@@ -0,0 +1,149 @@
+use assert_cmd::prelude::*;
+use predicates::prelude::*;
+
+mod common;
+
+use common::anno;
+
+#[test]
+fn supported_protocols_offered() -> Result<(), Box<dyn std::error::Error>> {
+  let mut cmd = anno();
+  cmd.args(["example.c", "-p", "protocols:"]);
+  cmd
+    .assert()
+    .success()
+    .stdout(predicate::str::contains("jsonl/1,lines | #include <stdio.h>"));
+  Ok(())
+}
+
+#[test]
+fn json_lines_gaps_filled() -> Result<(), Box<dyn std::error::Error>> {
+  let mut cmd = anno();
+  cmd.args(["example.c", "-p", "cat:output/sparse.jsonl", "-p", "numbers:"]);
+  cmd
+    .assert()
+    .success()
+    .stdout(predicate::str::starts_with("cat | n… | \n    | 1  | #include <stdio.h>\n"))
+    .stdout(predicate::str::contains("x z | 3  | int square(int x) {\n"))
+    // Warning severity is highlighted
+    .stdout(predicate::str::is_match(r"\x1b\[33my\s*\x1b\[0m \| 9  \|   int value").unwrap())
+    .stdout(predicate::str::contains("    | 12 | }\n"));
+  Ok(())
+}
+
+#[test]
+fn json_lines_out_of_range_rejected() -> Result<(), Box<dyn std::error::Error>> {
+  let mut cmd = anno();
+  cmd.args(["example.c", "-p", "cat:output/out-of-range.jsonl"]);
+  cmd.assert().failure().stderr(predicate::str::contains(
+    "Annotation producer `cat` supplied an annotation for line 13, but the file being annotated has 12 lines",
+  ));
+  Ok(())
+}
+
+#[test]
+fn json_lines_out_of_range_truncated() -> Result<(), Box<dyn std::error::Error>> {
+  let mut cmd = anno();
+  cmd.args([
+    "example.c",
+    "-p",
+    "cat:output/out-of-range.jsonl",
+    "--on-mismatch=truncate",
+  ]);
+  cmd
+    .assert()
+    .success()
+    .stdout(predicate::str::contains("x | int square(int x) {\n").not())
+    .stdout(predicate::str::contains("x |   int result = x * x;\n"))
+    .stderr(predicate::str::contains("Dropping annotation for line 13 from `cat`"));
+  Ok(())
+}
+
+#[test]
+fn json_lines_ranges_out_of_range_clipped() -> Result<(), Box<dyn std::error::Error>> {
+  let mut cmd = anno();
+  cmd.args([
+    "example.c",
+    "-p",
+    "cat:output/ranges-out-of-range.jsonl",
+    "--on-mismatch=truncate",
+  ]);
+  // Ranges keep the lines within the file, whichever end lies outside it
+  cmd
+    .assert()
+    .success()
+    .stdout(predicate::str::contains("s | #include <stdio.h>
+s | 
+  | int square(int x) {
+"))
+    .stdout(predicate::str::contains("  |   printf(\"%d\\n\", value);\ne |   return 0;\ne | }\n"))
+    .stdout(predicate::str::contains("x |").not());
+  Ok(())
+}
+
+#[test]
+fn json_lines_invalid_record_rejected() -> Result<(), Box<dyn std::error::Error>> {
+  let mut cmd = anno();
+  cmd.args(["example.c", "-p", "cat:output/invalid.jsonl"]);
+  cmd.assert().failure().stderr(predicate::str::contains(
+    "Annotation producer `cat` supplied an invalid record on output line 3: {\"lines\": 5}",
+  ));
+  Ok(())
+}
+
+#[test]
+fn unsupported_protocol_rejected() -> Result<(), Box<dyn std::error::Error>> {
+  let mut cmd = anno();
+  cmd.args(["example.c", "-p", "cat:output/unsupported.jsonl"]);
+  cmd.assert().failure().stderr(predicate::str::contains(
+    "Annotation producer `cat` uses unsupported protocol `jsonl/2`",
+  ));
+  Ok(())
+}
+
+#[test]
+fn json_lines_ranges_underlined() -> Result<(), Box<dyn std::error::Error>> {
+  let mut cmd = anno();
+  cmd.args(["example.c", "-p", "cat:output/ranges.jsonl"]);
+  cmd
+    .assert()
+    .success()
+    .stdout(predicate::str::contains("f   | int square(int x) {\n"))
+    .stdout(predicate::str::contains(
+      "x f |   int result = \x1b[4mx * x\x1b[0m;\n",
+    ))
+    .stdout(predicate::str::contains(
+      "v   |   int \x1b[4mvalue = square(7);\x1b[0m\n",
+    ))
+    .stdout(predicate::str::contains(
+      "v   | \x1b[4m  printf\x1b[0m(\"%d\\n\", value);\n",
+    ));
+  Ok(())
+}
+
+#[test]
+fn json_lines_backwards_range_rejected() -> Result<(), Box<dyn std::error::Error>> {
+  let mut cmd = anno();
+  cmd.args(["example.c", "-p", "cat:output/backwards-range.jsonl"]);
+  cmd
+    .assert()
+    .failure()
+    .stderr(predicate::str::contains("supplied an invalid record on output line 2"))
+    .stderr(predicate::str::contains("Record must have either a `line` or a valid `range`"));
+  Ok(())
+}
+
+#[test]
+fn json_lines_invalid_columns_rejected() -> Result<(), Box<dyn std::error::Error>> {
+  // Columns start from 1, and cannot end before they start on the same line
+  for output in ["zero-column", "backwards-columns"] {
+    let mut cmd = anno();
+    cmd.args(["example.c", "-p", &format!("cat:output/{}.jsonl", output)]);
+    cmd
+      .assert()
+      .failure()
+      .stderr(predicate::str::contains("supplied an invalid record on output line 2"))
+      .stderr(predicate::str::contains("Record must have either a `line` or a valid `range`"));
+  }
+  Ok(())
+}
@@ -0,0 +1,107 @@
+use std::time::{Duration, Instant};
+
+use assert_cmd::prelude::*;
+use predicates::prelude::*;
+
+mod common;
+
+use common::anno;
+
+#[test]
+fn producers_run_in_parallel() -> Result<(), Box<dyn std::error::Error>> {
+  // Producers which do not describe themselves also sleep when asked to, so
+  // this covers asking for descriptions in parallel too
+  let mut cmd = anno();
+  cmd.args([
+    "example.c",
+    "-p",
+    "sleep-undescribed:1",
+    "-p",
+    "sleep-undescribed:1",
+    "-p",
+    "sleep-undescribed:1",
+  ]);
+  let started = Instant::now();
+  cmd.assert().success();
+  assert!(started.elapsed() < Duration::from_millis(2500));
+  Ok(())
+}
+
+#[test]
+fn output_follows_producer_order() -> Result<(), Box<dyn std::error::Error>> {
+  let mut cmd = anno();
+  cmd.args(["example.c", "-p", "sleep:0.5", "-p", "dwarf-line-table:"]);
+  cmd
+    .assert()
+    .success()
+    .stdout(predicate::str::contains("3  | x | int square(int x) {"));
+  Ok(())
+}
+
+#[test]
+fn producer_timeout_kills_producer() -> Result<(), Box<dyn std::error::Error>> {
+  let mut cmd = anno();
+  cmd.args(["example.c", "-p", "sleep:5", "--producer-timeout", "0.2"]);
+  let started = Instant::now();
+  cmd
+    .assert()
+    .failure()
+    .stderr(predicate::str::contains("`anno-sleep` timed out after 200ms and was killed"));
+  assert!(started.elapsed() < Duration::from_secs(4));
+  Ok(())
+}
+
+#[test]
+fn named_producer_timeout() -> Result<(), Box<dyn std::error::Error>> {
+  let mut cmd = anno();
+  cmd.args([
+    "example.c",
+    "-p",
+    "sleep:5",
+    "-p",
+    "numbers:",
+    "--producer-timeout",
+    "numbers=10",
+    "--producer-timeout",
+    "sleep=0.2",
+  ]);
+  cmd
+    .assert()
+    .failure()
+    .stderr(predicate::str::contains("Annotation producer `anno-sleep` timed out"));
+  Ok(())
+}
+
+#[test]
+fn producer_timeout_needs_name() -> Result<(), Box<dyn std::error::Error>> {
+  let mut cmd = anno();
+  cmd.args(["example.c", "-p", "numbers:", "--producer-timeout", "=5"]);
+  cmd.assert().failure().stderr(predicate::str::contains("`=5` has no producer name"));
+  Ok(())
+}
+
+#[test]
+fn global_timeout() -> Result<(), Box<dyn std::error::Error>> {
+  let mut cmd = anno();
+  cmd.args(["example.c", "-p", "sleep:5", "-p", "sleep:5", "--timeout", "0.2"]);
+  let started = Instant::now();
+  cmd
+    .assert()
+    .failure()
+    .stderr(predicate::str::contains("2 annotation producers failed"))
+    .stderr(predicate::str::contains("`anno-sleep` timed out after 200ms"));
+  assert!(started.elapsed() < Duration::from_secs(4));
+  Ok(())
+}
+
+#[test]
+fn all_failures_reported() -> Result<(), Box<dyn std::error::Error>> {
+  let mut cmd = anno();
+  cmd.args(["example.c", "-p", "fail:", "-p", "numbers:", "-p", "missing:"]);
+  cmd
+    .assert()
+    .failure()
+    .stderr(predicate::str::contains("`anno-fail` exited unsuccessfully"))
+    .stderr(predicate::str::contains("`anno-missing` could not be run"));
+  Ok(())
+}
@@ -0,0 +1,75 @@
+use assert_cmd::prelude::*;
+use predicates::prelude::*;
+
+mod common;
+
+use common::anno;
+
+#[test]
+fn lines_selected() -> Result<(), Box<dyn std::error::Error>> {
+  let mut cmd = anno();
+  cmd.args(["example.c", "-p", "numbers", "--lines", "9,3-4"]);
+  cmd.assert().success().stdout(
+    "n… | \n\
+     3  | int square(int x) {\n\
+     4  |   int result = x * x;\n\
+     9  |   int value = square(7);\n",
+  );
+  Ok(())
+}
+
+#[test]
+fn function_selected() -> Result<(), Box<dyn std::error::Error>> {
+  let mut cmd = anno();
+  cmd.args(["example.c", "-p", "numbers", "--function", "square"]);
+  cmd.assert().success().stdout(
+    "n… | \n\
+     3  | int square(int x) {\n\
+     4  |   int result = x * x;\n\
+     5  |   return result;\n\
+     6  | }\n",
+  );
+  Ok(())
+}
+
+#[test]
+fn function_missing() -> Result<(), Box<dyn std::error::Error>> {
+  let mut cmd = anno();
+  cmd.args(["example.c", "-p", "numbers", "--function", "printf"]);
+  cmd.assert().failure().stderr(predicate::str::contains(
+    "Unable to find a definition of function `printf`",
+  ));
+  Ok(())
+}
+
+#[test]
+fn lines_invalid() -> Result<(), Box<dyn std::error::Error>> {
+  let mut cmd = anno();
+  cmd.args(["example.c", "-p", "numbers", "--lines", "5-2"]);
+  cmd
+    .assert()
+    .failure()
+    .stderr(predicate::str::contains("`5-2` is not a line range"));
+  Ok(())
+}
+
+#[test]
+fn line_ranges_given_to_producers() -> Result<(), Box<dyn std::error::Error>> {
+  let mut cmd = anno();
+  cmd.args([
+    "example.c",
+    "-p",
+    "env:?var=ANNO_LINE_RANGES",
+    "--function",
+    "main",
+    "--lines",
+    "1",
+    "--format",
+    "jsonl",
+  ]);
+  cmd
+    .assert()
+    .success()
+    .stdout(predicate::str::contains(r#""env":{"value":"1-1,8-12"}"#));
+  Ok(())
+}
@@ -0,0 +1,102 @@
+use assert_cmd::{prelude::*, Command};
+use predicates::prelude::*;
+
+mod common;
+
+use common::anno;
+
+#[test]
+fn stdin_annotated_with_target_name() -> Result<(), Box<dyn std::error::Error>> {
+  let mut cmd = Command::from_std(anno());
+  cmd.args(["-", "--target-name", "src/buffer.c", "-p", "numbers", "-p", "content"]);
+  cmd.write_stdin("int x;\nint y;\n");
+  // Producers read the piped content rather than any file on disk
+  cmd
+    .assert()
+    .success()
+    .stdout("… | conte… | \n1 | int x; | int x;\n2 | int y; | int y;\n");
+  Ok(())
+}
+
+#[test]
+fn stdin_target_paths() -> Result<(), Box<dyn std::error::Error>> {
+  let mut cmd = Command::from_std(anno());
+  cmd.args([
+    "-",
+    "--target-name",
+    "src/buffer.c",
+    "-p",
+    "env:?var=ANNO_TARGET",
+    "-p",
+    "env:?var=ANNO_TARGET_NAME",
+    "--format",
+    "json",
+  ]);
+  cmd.write_stdin("int x;\n");
+  let output = cmd.assert().success().get_output().stdout.clone();
+  let output: serde_json::Value = serde_json::from_slice(&output)?;
+  assert_eq!(output["target"], "src/buffer.c");
+  assert_eq!(output["line_count"], 1);
+  let annotations = &output["lines"][0]["annotations"];
+  let target = annotations["env:?var=ANNO_TARGET"]["value"].as_str().unwrap();
+  let target_name = annotations["env:?var=ANNO_TARGET_NAME"]["value"].as_str().unwrap();
+  // Content is written to a temporary file with the same name
+  assert!(target.ends_with("/buffer.c"));
+  assert!(!target.ends_with("/src/buffer.c"));
+  assert!(target_name.ends_with("/fixtures/src/buffer.c"));
+  Ok(())
+}
+
+#[test]
+fn stdin_matched_by_target_name() -> Result<(), Box<dyn std::error::Error>> {
+  // Data keyed by source path applies to the named target, not the temporary
+  // file holding its content
+  let mut cmd = Command::from_std(anno());
+  cmd.args([
+    "-",
+    "--target-name",
+    "src/buffer.c",
+    "-p",
+    "numbers",
+    "-p",
+    "line-table-by-path:output/lines-by-path.txt",
+  ]);
+  cmd.write_stdin("int x;\nint y;\n");
+  cmd.assert().success().stdout("… | … | \n1 |   | int x;\n2 | x | int y;\n");
+  Ok(())
+}
+
+#[test]
+fn target_name_with_file() -> Result<(), Box<dyn std::error::Error>> {
+  let mut cmd = anno();
+  cmd.args(["example.c", "--target-name", "other.c", "-p", "numbers", "--format", "jsonl"]);
+  cmd
+    .assert()
+    .success()
+    .stdout(predicate::str::starts_with(r#"{"target":"other.c","line_count":12,"#));
+  Ok(())
+}
+
+#[test]
+fn stdin_tui_unsupported() -> Result<(), Box<dyn std::error::Error>> {
+  let mut cmd = Command::from_std(anno());
+  cmd.args(["-", "-p", "numbers", "--tui"]);
+  cmd.write_stdin("int x;\n");
+  cmd.assert().failure().stderr(predicate::str::contains(
+    "`--tui` cannot read the file to annotate from stdin",
+  ));
+  Ok(())
+}
+
+#[test]
+fn range_columns_count_bytes() -> Result<(), Box<dyn std::error::Error>> {
+  // `é` takes two bytes, so column 4 is the `=`
+  let mut cmd = Command::from_std(anno());
+  cmd.args(["-", "-p", "cat:output/byte-columns.jsonl"]);
+  cmd.write_stdin("é = 1;\n");
+  cmd
+    .assert()
+    .success()
+    .stdout(predicate::str::contains("x | é \x1b[4m=\x1b[0m 1;\n"));
+  Ok(())
+}
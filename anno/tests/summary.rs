@@ -0,0 +1,71 @@
+use assert_cmd::prelude::*;
+use predicates::prelude::*;
+
+mod common;
+
+use common::anno;
+
+#[test]
+fn summary_text() -> Result<(), Box<dyn std::error::Error>> {
+  let mut cmd = anno();
+  cmd.args([
+    "example.c",
+    "-p",
+    "computable-expressions",
+    "-p",
+    "dwarf-line-table",
+    "-p",
+    "numbers",
+    "--diff-columns",
+    "1",
+    "--summary",
+  ]);
+  cmd
+    .assert()
+    .success()
+    .stdout(predicate::str::starts_with("12 lines\n"))
+    .stdout(predicate::str::contains("dwarf-line-table       |     4 |        4\n"))
+    .stdout(predicate::str::contains("numbers                |    12 |       12\n"))
+    .stdout(predicate::str::contains(
+      "Lines    |     2 |             4 |             2 |   0.250 |     0.500 |  0.333\n",
+    ))
+    // Only the selected columns are compared
+    .stdout(predicate::str::contains("compared with").count(1));
+  Ok(())
+}
+
+#[test]
+fn summary_json_sets() -> Result<(), Box<dyn std::error::Error>> {
+  let mut cmd = anno();
+  cmd.args([
+    "example.c",
+    "-p",
+    "cat:output/vars-before.jsonl",
+    "-p",
+    "cat:output/vars-after.jsonl",
+    "--summary",
+    "--format",
+    "json",
+  ]);
+  let output = cmd.assert().success().get_output().stdout.clone();
+  let summary: serde_json::Value = serde_json::from_slice(&output)?;
+  let comparison = &summary["comparisons"][0];
+  assert_eq!(comparison["lines"]["both"], 2);
+  assert_eq!(comparison["lines"]["recall"], 2.0 / 3.0);
+  assert_eq!(
+    comparison["elements"],
+    serde_json::json!({
+      "both": 3,
+      "only_baseline": 1,
+      "only_compared": 2,
+      "jaccard": 0.5,
+      "precision": 0.6,
+      "recall": 0.75,
+    })
+  );
+  assert_eq!(
+    comparison["coverage"][0],
+    serde_json::json!({ "element": "result", "baseline_lines": 2, "covered_lines": 1, "ratio": 0.5 })
+  );
+  Ok(())
+}
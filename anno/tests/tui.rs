@@ -0,0 +1,27 @@
+use assert_cmd::prelude::*;
+use predicates::prelude::*;
+
+mod common;
+
+use common::anno;
+
+#[test]
+fn tui_requires_terminal() -> Result<(), Box<dyn std::error::Error>> {
+  let mut cmd = anno();
+  cmd.args(["example.c", "-p", "numbers", "--tui"]);
+  cmd.assert().failure().stderr(predicate::str::contains(
+    "`--tui` can only be used when writing to a terminal",
+  ));
+  Ok(())
+}
+
+#[test]
+fn tui_conflicts_with_format() -> Result<(), Box<dyn std::error::Error>> {
+  let mut cmd = anno();
+  cmd.args(["example.c", "-p", "numbers", "--tui", "--format", "json"]);
+  cmd
+    .assert()
+    .failure()
+    .stderr(predicate::str::contains("cannot be used with"));
+  Ok(())
+}
@@ -0,0 +1,68 @@
+use assert_cmd::prelude::*;
+use predicates::prelude::*;
+
+mod common;
+
+use common::anno;
+
+#[test]
+fn extra_lines_rejected() -> Result<(), Box<dyn std::error::Error>> {
+  let mut cmd = anno();
+  cmd.args(["example.c", "-p", "numbers:", "-p", "offset:2"]);
+  cmd
+    .assert()
+    .failure()
+    .stderr(predicate::str::contains(
+      "Annotation producer `offset` produced 14 lines of output, but the file being annotated has 12 lines",
+    ))
+    .stderr(predicate::str::contains("first unexpected line: 13: \"13\""))
+    .stderr(predicate::str::contains("--on-mismatch=truncate"));
+  Ok(())
+}
+
+#[test]
+fn missing_lines_rejected() -> Result<(), Box<dyn std::error::Error>> {
+  let mut cmd = anno();
+  cmd.args(["example.c", "-p", "offset:-3"]);
+  cmd
+    .assert()
+    .failure()
+    .stderr(predicate::str::contains("produced 9 lines of output"))
+    .stderr(predicate::str::contains("first line without an annotation: 10"))
+    .stderr(predicate::str::contains("--on-mismatch=pad"));
+  Ok(())
+}
+
+#[test]
+fn pad_fills_missing_lines() -> Result<(), Box<dyn std::error::Error>> {
+  let mut cmd = anno();
+  cmd.args(["example.c", "-p", "offset:-3", "--on-mismatch=pad"]);
+  cmd
+    .assert()
+    .success()
+    .stdout(predicate::str::contains("9 |   int value = square(7);\n"))
+    .stdout(predicate::str::contains("  |   return 0;\n"))
+    .stderr(predicate::str::contains("Padding output from `offset` with 3 blank annotations"));
+  Ok(())
+}
+
+#[test]
+fn pad_still_rejects_extra_lines() -> Result<(), Box<dyn std::error::Error>> {
+  let mut cmd = anno();
+  cmd.args(["example.c", "-p", "offset:1", "--on-mismatch=pad"]);
+  cmd.assert().failure();
+  Ok(())
+}
+
+#[test]
+fn truncate_drops_extra_lines() -> Result<(), Box<dyn std::error::Error>> {
+  let mut cmd = anno();
+  cmd.args(["example.c", "-p", "offset:5", "--on-mismatch=truncate"]);
+  cmd
+    .assert()
+    .success()
+    .stdout(predicate::str::contains("12 | }\n"))
+    .stdout(predicate::str::contains("13").not())
+    .stderr(predicate::str::contains("Dropping 5 extra lines of output from `offset`"));
+  Ok(())
+}
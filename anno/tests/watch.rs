@@ -0,0 +1,73 @@
+use std::{
+  fs,
+  io::{BufRead, BufReader},
+  process::Stdio,
+  sync::mpsc,
+  thread,
+  time::Duration,
+};
+
+use serde_json::Value;
+
+mod common;
+
+use common::anno;
+
+#[test]
+fn watch_reruns_changed_producers() -> Result<(), Box<dyn std::error::Error>> {
+  let dir = tempfile::tempdir()?;
+  let target = dir.path().join("target.c");
+  let source = dir.path().join("source.jsonl");
+  let runs = dir.path().join("runs");
+  fs::write(&target, "a\nb\n")?;
+  fs::write(&source, "{\"protocol\": \"jsonl\", \"version\": 1}\n{\"line\": 1, \"value\": \"x\"}\n")?;
+
+  let mut child = anno()
+    .arg(&target)
+    .args(["--watch", "--format", "jsonl", "-p"])
+    .arg(format!("cat:{}", source.display()))
+    .arg("-p")
+    .arg(format!("runs:?file={}", runs.display()))
+    .stdout(Stdio::piped())
+    .spawn()?;
+
+  // Read output on another thread so the test can time out
+  let (sender, receiver) = mpsc::channel();
+  let stdout = BufReader::new(child.stdout.take().unwrap());
+  thread::spawn(move || {
+    for line in stdout.lines() {
+      let line: Value = serde_json::from_str(&line.unwrap()).unwrap();
+      if sender.send(line).is_err() {
+        break;
+      }
+    }
+  });
+  // Each run writes a header and then each line
+  let next_run = || -> Vec<Value> {
+    let header = receiver.recv_timeout(Duration::from_secs(10)).unwrap();
+    let line_count = header["line_count"].as_u64().unwrap();
+    (0..line_count)
+      .map(|_| receiver.recv_timeout(Duration::from_secs(10)).unwrap())
+      .collect()
+  };
+
+  let lines = next_run();
+  assert_eq!(lines[0]["annotations"]["cat"]["value"], "x");
+  assert_eq!(lines[0]["annotations"]["runs"]["value"], "1");
+
+  // Only the producer using the changed data source runs again
+  fs::write(&source, "{\"protocol\": \"jsonl\", \"version\": 1}\n{\"line\": 1, \"value\": \"y\"}\n")?;
+  let lines = next_run();
+  assert_eq!(lines[0]["annotations"]["cat"]["value"], "y");
+  assert_eq!(lines[0]["annotations"]["runs"]["value"], "1");
+
+  // Every producer runs again when the target changes
+  fs::write(&target, "a\nb\nc\n")?;
+  let lines = next_run();
+  assert_eq!(lines.len(), 3);
+  assert_eq!(lines[0]["annotations"]["runs"]["value"], "2");
+
+  child.kill()?;
+  child.wait()?;
+  Ok(())
+}
@@ -1,22 +1,44 @@
-use std::{borrow, collections::HashSet, env, fs, path};
-
+use std::{
+    borrow,
+    collections::{HashMap, HashSet},
+    fs, path,
+    process::ExitCode,
+};
+
+use anno_producer::{Description, Output, ValueKind};
 use anyhow::{Context, Result};
 use log::{debug, trace};
 use object::{Object, ObjectSection};
 
-fn main() -> Result<()> {
-    env_logger::init();
+fn main() -> ExitCode {
+    // Debug info is only read once, even when annotating several files
+    let mut lines_by_file: Option<HashMap<path::PathBuf, HashSet<u64>>> = None;
+    let description = Description::new(
+        "debug-line-table",
+        "Marks lines present in the line table of DWARF debug info",
+    )
+    .requires_source("Path to debug info")
+    .values(ValueKind::Boolean)
+    .legend("x", "Line is in the line table");
+    anno_producer::run_each(&description, |context| {
+        let lines_by_file = match &mut lines_by_file {
+            Some(lines_by_file) => lines_by_file,
+            None => lines_by_file.insert(read_debug_info(
+                context.require_source("Path to debug info")?,
+            )?),
+        };
+        let mut output = Output::new(context);
+        if let Some(lines_present) = lines_by_file.get(&context.target_name) {
+            output.mark_lines(lines_present.iter().map(|&line| line as usize), "x");
+        }
+        Ok(output)
+    })
+}
 
-    let source_file_path = env::var("ANNO_TARGET")?;
-    let debug_info_path = env::var("ANNO_SOURCE")?;
-    let file = fs::File::open(&debug_info_path)
-        .with_context(|| {
-            if debug_info_path.is_empty() {
-                format!("Path to debug info is required")
-            } else {
-                format!("Unable to open debug info ({})", debug_info_path)
-            }
-        })?;
+/// Collects the lines present in the line table for every source file
+fn read_debug_info(debug_info_path: &str) -> Result<HashMap<path::PathBuf, HashSet<u64>>> {
+    let file = fs::File::open(debug_info_path)
+        .with_context(|| format!("Unable to open debug info ({})", debug_info_path))?;
     let mmap = unsafe { memmap2::Mmap::map(&file)? };
     let object = object::File::parse(&*mmap)?;
     let endian = if object.is_little_endian() {
@@ -24,25 +46,14 @@ fn main() -> Result<()> {
     } else {
         gimli::RunTimeEndian::Big
     };
-    let lines_present = collect_lines(&object, endian, &source_file_path)?;
-
-    let line_count: usize = env::var("ANNO_TARGET_LINES")?.parse()?;
-    for i in 0..line_count {
-        if lines_present.contains(&((i + 1) as u64)) {
-            println!("x");
-        } else {
-            println!(" ");
-        }
-    }
-    Ok(())
+    collect_lines(&object, endian)
 }
 
 // Adapted from https://github.com/gimli-rs/gimli/blob/master/examples/simple_line.rs
 fn collect_lines(
     object: &object::File,
     endian: gimli::RunTimeEndian,
-    source_file_path: &str,
-) -> Result<HashSet<u64>> {
+) -> Result<HashMap<path::PathBuf, HashSet<u64>>> {
     // Load a section and return as `Cow<[u8]>`.
     let load_section = |id: gimli::SectionId| -> Result<borrow::Cow<[u8]>, gimli::Error> {
         match object.section_by_name(id.name()) {
@@ -66,7 +77,7 @@ fn collect_lines(
     let dwarf = dwarf_cow.borrow(&borrow_section);
 
     // Iterate over the compilation units.
-    let mut lines_present = HashSet::new();
+    let mut lines_by_file: HashMap<path::PathBuf, HashSet<u64>> = HashMap::new();
     let mut iter = dwarf.units();
     while let Some(header) = iter.next()? {
         trace!(
@@ -112,11 +123,6 @@ fn collect_lines(
                         );
                     }
 
-                    // Skip if path doesn't match source file path
-                    if path.to_str().unwrap() != source_file_path {
-                        continue;
-                    }
-
                     // Determine line/column. DWARF line/column is never 0, so we use that
                     // but other applications may want to display this differently.
                     let line = match row.line() {
@@ -130,11 +136,11 @@ fn collect_lines(
 
                     debug!("{:x} {}:{}:{}", row.address(), path.display(), line, column);
 
-                    // Add line to set of present lines
-                    lines_present.insert(line);
+                    // Add line to set of present lines for this file
+                    lines_by_file.entry(path).or_default().insert(line);
                 }
             }
         }
     }
-    Ok(lines_present)
+    Ok(lines_by_file)
 }
@@ -1,9 +1,12 @@
 use std::{
     borrow::{Borrow, Cow},
-    env, fs, path::{PathBuf, Path},
+    fs,
+    path::Path,
+    process::ExitCode,
 };
 
-use anyhow::{Context, Result};
+use anno_producer::{Description, Output, ProducerContext, ValueKind};
+use anyhow::Context;
 use debuginfo_quality::{evaluate_info, Stats};
 use linked_hash_set::LinkedHashSet;
 use log::{debug, trace};
@@ -11,36 +14,31 @@ use object::{Object, ObjectSection};
 use path_absolutize::Absolutize;
 use typed_arena::Arena;
 
-fn main() -> Result<()> {
-    env_logger::init();
+fn main() -> ExitCode {
+    let description = Description::new(
+        "debug-vars-locatable",
+        "Lists variables locatable on each line according to DWARF debug info",
+    )
+    .requires_source("Path to debug info")
+    .values(ValueKind::Set);
+    anno_producer::run(&description, |context| {
+        let debug_info_path = context.require_source("Path to debug info")?;
+        let file = fs::File::open(debug_info_path)
+            .with_context(|| format!("Unable to open debug info ({})", debug_info_path))?;
+        let mmap = unsafe { memmap2::Mmap::map(&file)? };
+        let object = object::File::parse(&*mmap)?;
 
-    let source_file_path = PathBuf::from(env::var("ANNO_TARGET")?);
-    let debug_info_path = env::var("ANNO_SOURCE")?;
-    let file = fs::File::open(&debug_info_path).with_context(|| {
-        if debug_info_path.is_empty() {
-            format!("Path to debug info is required")
-        } else {
-            format!("Unable to open debug info ({})", debug_info_path)
-        }
-    })?;
-    let mmap = unsafe { memmap2::Mmap::map(&file)? };
-    let object = object::File::parse(&*mmap)?;
-
-    let line_count: usize = env::var("ANNO_TARGET_LINES")?.parse()?;
-    let variable_locations = collect_variable_locations(&object);
-    let defined_variables_per_line =
-        defined_variables_per_line(&variable_locations, &source_file_path, line_count);
+        let variable_locations = collect_variable_locations(&object);
+        let defined_variables_per_line = defined_variables_per_line(&variable_locations, context);
 
-    for i in 0..line_count {
-        let defined_variables = &defined_variables_per_line[i];
-        if defined_variables.is_empty() {
-            println!(" ");
-            continue;
+        let mut output = Output::new(context);
+        output.use_sets();
+        for (i, defined_variables) in defined_variables_per_line.into_iter().enumerate() {
+            // Lines are 1-based
+            output.add_list(i + 1, defined_variables);
         }
-        let collected_variables = defined_variables.iter().cloned().collect::<Vec<_>>();
-        println!("{}", collected_variables.join(" "));
-    }
-    Ok(())
+        Ok(output)
+    })
 }
 
 // Adapted from debuginfo-quality/src/main.rs
@@ -99,14 +97,27 @@ fn collect_variable_locations(file: &object::File) -> Stats {
 
 fn defined_variables_per_line(
     variable_locations: &Stats,
-    source_file_path: &PathBuf,
-    line_count: usize,
+    context: &ProducerContext,
 ) -> Vec<LinkedHashSet<String>> {
+    let source_file_path = context.target_name.as_path();
     let mut defined_variables_per_line: Vec<LinkedHashSet<String>> = Vec::new();
-    defined_variables_per_line.resize_with(line_count, Default::default);
+    defined_variables_per_line.resize_with(context.line_count, Default::default);
+
+    // Lines are 1-based
+    let wanted = |line: usize| line >= 1 && line <= context.line_count && context.wants_line(line);
 
     for func in &variable_locations.output {
         for var in &func.variables {
+            // Skip variables only covering lines that will not be shown,
+            // before the work of resolving their paths
+            if !var
+                .extra
+                .source_line_set_covered
+                .iter()
+                .any(|&line| wanted(line as usize))
+            {
+                continue;
+            }
             // Some paths are already absolute, others are relative to compilation
             let mut decl_file_path = if Path::new(&var.decl_dir).is_absolute() {
                 Path::new(&var.decl_dir).join(&var.decl_file)
@@ -130,9 +141,12 @@ fn defined_variables_per_line(
                 &var.name, &var.decl_file, &var.decl_line
             );
             debug!("Source line set: {:?}", &var.extra.source_line_set_covered);
-            // Lines are 1-based
-            for line in &var.extra.source_line_set_covered {
-                let defined_variables = &mut defined_variables_per_line[(line - 1) as usize];
+            for &line in &var.extra.source_line_set_covered {
+                let line = line as usize;
+                if !wanted(line) {
+                    continue;
+                }
+                let defined_variables = &mut defined_variables_per_line[line - 1];
                 defined_variables.insert(var.name.clone());
             }
         }
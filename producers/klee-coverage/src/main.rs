@@ -1,46 +1,42 @@
 use std::{
     collections::HashSet,
-    env,
     fs::{self, ReadDir},
-    path::PathBuf,
+    path::Path,
+    process::ExitCode,
 };
 
+use anno_producer::{Description, Output, ValueKind};
 use anyhow::{anyhow, Context, Result};
 use log::trace;
 
-fn main() -> Result<()> {
-    env_logger::init();
-
-    let source_file_path = PathBuf::from(env::var("ANNO_TARGET")?);
-    let klee_output_dir_path = env::var("ANNO_SOURCE")?;
-    let klee_output_dir = fs::read_dir(&klee_output_dir_path).with_context(|| {
-        if klee_output_dir_path.is_empty() {
-            format!("Path to KLEE output directory is required")
-        } else {
+fn main() -> ExitCode {
+    let description = Description::new(
+        "klee-coverage",
+        "Marks lines covered according to KLEE statistics",
+    )
+    .requires_source("Path to KLEE output directory")
+    .values(ValueKind::Boolean)
+    .legend("x", "Line was covered");
+    anno_producer::run(&description, |context| {
+        let klee_output_dir_path = context.require_source("Path to KLEE output directory")?;
+        let klee_output_dir = fs::read_dir(klee_output_dir_path).with_context(|| {
             format!(
                 "Unable to open KLEE output directory ({})",
                 klee_output_dir_path
             )
-        }
-    })?;
+        })?;
 
-    let line_count: usize = env::var("ANNO_TARGET_LINES")?.parse()?;
-    let covered_lines = collect_covered_lines(klee_output_dir, &source_file_path, line_count)?;
+        let covered_lines = collect_covered_lines(klee_output_dir, &context.target_name)?;
 
-    for i in 0..line_count {
-        if covered_lines.contains(&(i + 1)) {
-            println!("x");
-        } else {
-            println!(" ");
-        }
-    }
-    Ok(())
+        let mut output = Output::new(context);
+        output.mark_lines(covered_lines, "x");
+        Ok(output)
+    })
 }
 
 fn collect_covered_lines(
     klee_output_dir: ReadDir,
-    source_file_path: &PathBuf,
-    _line_count: usize,
+    source_file_path: &Path,
 ) -> Result<HashSet<usize>> {
     let mut covered_lines = HashSet::new();
 
@@ -1,11 +1,16 @@
-use std::env;
+use std::process::ExitCode;
 
-use anyhow::Result;
+use anno_producer::{Description, Output, ValueKind};
 
-fn main() -> Result<()> {
-  let line_count: usize = env::var("ANNO_TARGET_LINES")?.parse()?;
-  for i in 0..line_count {
-    println!("{}", i + 1);
-  }
-  Ok(())
+fn main() -> ExitCode {
+    let description = Description::new("numbers", "Numbers each line of the file")
+        .not_diffable()
+        .values(ValueKind::Number);
+    anno_producer::run(&description, |context| {
+        let mut output = Output::new(context);
+        for line in 1..=context.line_count {
+            output.add(line, line.to_string());
+        }
+        Ok(output)
+    })
 }
@@ -1,32 +1,35 @@
-use std::{collections::HashSet, env, fs, path::PathBuf, process::ExitCode};
+use std::{env, fs, path::Path, process::ExitCode};
 
+use anno_producer::{Description, Output, Position, Range, ValueKind};
 use anyhow::{Context, Result};
 use duct::cmd;
 use log::debug;
 
-fn main() -> Result<ExitCode> {
-    env_logger::init();
+fn main() -> ExitCode {
+    let description = Description::new(
+        "source-computation",
+        "Marks lines of C source with computation (using `dbgcov`)",
+    )
+    .values(ValueKind::Boolean)
+    .legend("x", "Line has computation")
+    .reads_env("CC")
+    .reads_env("CFLAGS");
+    anno_producer::run(&description, |context| {
+        context.require_extension("c")?;
 
-    let source_file_path = PathBuf::from(env::var("ANNO_TARGET")?);
-    if source_file_path.extension().unwrap() != "c" {
-        eprintln!("Error: Only `.c` files are currently supported");
-        return Ok(ExitCode::FAILURE);
-    };
+        let regions_with_computation = collect_regions(&context.target)?;
 
-    let lines_with_computation = collect_lines(&source_file_path)?;
-
-    let line_count: usize = env::var("ANNO_TARGET_LINES")?.parse()?;
-    for i in 0..line_count {
-        if lines_with_computation.contains(&(i + 1)) {
-            println!("x");
-        } else {
-            println!(" ");
+        // Regions are expanded to whole lines if Anno lacks range support
+        let mut output = Output::new(context);
+        for region in regions_with_computation {
+            output.add_range(region, "x");
         }
-    }
-    Ok(ExitCode::SUCCESS)
+        Ok(output)
+    })
 }
 
-fn collect_lines(source_file_path: &PathBuf) -> Result<HashSet<usize>> {
+/// Collects source regions reported by `dbgcov` as containing computation
+fn collect_regions(source_file_path: &Path) -> Result<Vec<Range>> {
     // TODO: Change `dbgcov` to only print to stdout by default...?
     let preprocessed_file_path = source_file_path.with_extension("i");
 
@@ -62,8 +65,8 @@ fn collect_lines(source_file_path: &PathBuf) -> Result<HashSet<usize>> {
     let regions = fs::read_to_string(&report_path)
         .with_context(|| format!("Unable to read `dbgcov` report ({})", report_path.display()))?;
 
-    // Collect set of lines with computation
-    let mut lines_with_computation = HashSet::new();
+    // Collect regions with computation
+    let mut regions_with_computation = Vec::new();
     for regions_line in regions.lines() {
         // Line format:
         // start as `file:line:column`\t
@@ -88,12 +91,18 @@ fn collect_lines(source_file_path: &PathBuf) -> Result<HashSet<usize>> {
         }
 
         debug!("Matching line: {}", regions_line);
-        let region_start_line: usize = region_start_parts.next().unwrap().parse()?;
-        let region_end_line: usize = region_end.split(':').nth(1).unwrap().parse()?;
-        for line in region_start_line..=region_end_line {
-            lines_with_computation.insert(line);
-        }
+        let mut region_end_parts = region_end.split(':').skip(1);
+        regions_with_computation.push(Range {
+            start: Position {
+                line: region_start_parts.next().unwrap().parse()?,
+                column: Some(region_start_parts.next().unwrap().parse()?),
+            },
+            end: Position {
+                line: region_end_parts.next().unwrap().parse()?,
+                column: Some(region_end_parts.next().unwrap().parse()?),
+            },
+        });
     }
 
-    Ok(lines_with_computation)
+    Ok(regions_with_computation)
 }
@@ -1,39 +1,40 @@
-use std::{env, fs, path::PathBuf, process::ExitCode};
+use std::{env, fs, path::Path, process::ExitCode};
 
+use anno_producer::{Description, Output, Position, Range, ValueKind};
 use anyhow::{Context, Result};
 use duct::cmd;
-use linked_hash_set::LinkedHashSet;
 use log::debug;
 
-fn main() -> Result<ExitCode> {
-    env_logger::init();
+fn main() -> ExitCode {
+    let description = Description::new(
+        "source-vars-must-be-defined",
+        "Lists variables which must be defined on each line of C source (using `dbgcov`)",
+    )
+    .values(ValueKind::Set)
+    .reads_env("CC")
+    .reads_env("CFLAGS");
+    anno_producer::run(&description, |context| {
+        context.require_extension("c")?;
 
-    let source_file_path = PathBuf::from(env::var("ANNO_TARGET")?);
-    if source_file_path.extension().unwrap() != "c" {
-        eprintln!("Error: Only `.c` files are currently supported");
-        return Ok(ExitCode::FAILURE);
-    };
+        let definition_regions = collect_definition_regions(&context.target)?;
 
-    let line_count: usize = env::var("ANNO_TARGET_LINES")?.parse()?;
-    let defined_variables_per_line =
-        collect_defined_variables_per_line(&source_file_path, line_count)?;
-
-    for i in 0..line_count {
-        let defined_variables = &defined_variables_per_line[i];
-        if defined_variables.is_empty() {
-            println!(" ");
-            continue;
+        // Regions are expanded to whole lines if Anno lacks range support
+        let mut output = Output::new(context);
+        output.use_sets();
+        for region in definition_regions {
+            output.add_range(region.range, region.variable);
         }
-        let collected_variables = defined_variables.iter().cloned().collect::<Vec<_>>();
-        println!("{}", collected_variables.join(" "));
-    }
-    Ok(ExitCode::SUCCESS)
+        Ok(output)
+    })
 }
 
-fn collect_defined_variables_per_line(
-    source_file_path: &PathBuf,
-    line_count: usize,
-) -> Result<Vec<LinkedHashSet<String>>> {
+/// Source region reported by `dbgcov` where a variable is defined
+struct DefinitionRegion {
+    variable: String,
+    range: Range,
+}
+
+fn collect_definition_regions(source_file_path: &Path) -> Result<Vec<DefinitionRegion>> {
     // TODO: Change `dbgcov` to only print to stdout by default...?
     let preprocessed_file_path = source_file_path.with_extension("i");
 
@@ -69,9 +70,8 @@ fn collect_defined_variables_per_line(
     let regions = fs::read_to_string(&report_path)
         .with_context(|| format!("Unable to read `dbgcov` report ({})", report_path.display()))?;
 
-    // Collect defined variables for each line
-    let mut defined_variables_per_line: Vec<LinkedHashSet<String>> = Vec::new();
-    defined_variables_per_line.resize_with(line_count, Default::default);
+    // Collect variable definition regions
+    let mut definition_regions = Vec::new();
     for regions_line in regions.lines() {
         // Line format:
         // start as `file:line:column`\t
@@ -100,16 +100,21 @@ fn collect_defined_variables_per_line(
         let variable_name = variable_description_parts.nth(1).unwrap();
 
         debug!("Matching line: {}", regions_line);
-        // Lines are 1-based
-        let region_start_line: usize = region_start_parts.next().unwrap().parse()?;
-        let region_end_line: usize = region_end.split(':').nth(1).unwrap().parse()?;
-        for line in region_start_line..=region_end_line {
-            let defined_variables = &mut defined_variables_per_line[line - 1];
-            if !defined_variables.contains(variable_name) {
-                defined_variables.insert(variable_name.to_string());
-            }
-        }
+        let mut region_end_parts = region_end.split(':').skip(1);
+        definition_regions.push(DefinitionRegion {
+            variable: variable_name.to_string(),
+            range: Range {
+                start: Position {
+                    line: region_start_parts.next().unwrap().parse()?,
+                    column: Some(region_start_parts.next().unwrap().parse()?),
+                },
+                end: Position {
+                    line: region_end_parts.next().unwrap().parse()?,
+                    column: Some(region_end_parts.next().unwrap().parse()?),
+                },
+            },
+        });
     }
 
-    Ok(defined_variables_per_line)
+    Ok(definition_regions)
 }